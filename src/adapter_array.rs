@@ -1,32 +1,332 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
-fn product_jolt_differences(adapters: Vec<usize>) -> usize {
-    let adapts: BTreeSet<usize> = adapters.into_iter().collect();
+fn product_jolt_differences(adapters: Vec<usize>) -> Result<usize, ChainError> {
+    let histogram = ChainModel::default().gap_histogram(&adapters)?;
+    let count = |gap| histogram.get(&gap).copied().unwrap_or(0);
 
-    let joltages = adapts
-        .iter()
-        .fold((0, 0, 0), |(prev, j1, j3), &cur| match cur - prev {
-            1 => (cur, j1 + 1, j3),
-            3 => (cur, j1, j3 + 1),
-            _ => (cur, j1, j3),
-        });
-
-    joltages.1 * (joltages.2 + 1)
+    Ok(count(1) * count(3))
 }
 
 /// Part2
-fn total_distinct_arrangements(adapters: Vec<usize>) -> u64 {
-    let adapts: BTreeSet<usize> = adapters.clone().into_iter().collect();
-    let max = *adapters.iter().max().unwrap();
-    let mut tracker: Vec<u64> = vec![0; max + 4];
-    tracker[max + 3] = 1;
+fn total_distinct_arrangements(adapters: Vec<usize>) -> u128 {
+    AdapterTable::new(&adapters)
+        .count()
+        .expect("more than u128::MAX arrangements")
+}
+
+/// Which joltage steps a chain of adapters may take
+#[derive(PartialEq, Debug, Clone)]
+struct ChainModel {
+    /// Differences allowed between one link in the chain and the next
+    gaps: BTreeSet<usize>,
+    /// How far above the largest adapter the device is rated
+    device_offset: usize,
+    /// How many of each listed adapter there are. Copies have the same
+    /// joltage, so one can only follow another if a gap of 0 is allowed.
+    copies: usize,
+}
+
+#[derive(PartialEq, Debug)]
+enum ChainError {
+    /// Using every adapter needs a step that isn't an allowed gap
+    GapNotAllowed { from: usize, to: usize },
+    /// No arrangement of the adapters reaches the device
+    NoChain,
+    /// There are more than `u128::MAX` arrangements
+    Overflow,
+}
+
+impl Default for ChainModel {
+    /// The rules from the puzzle
+    fn default() -> Self {
+        ChainModel::new(&[1, 2, 3], 3, 1)
+    }
+}
+
+impl ChainModel {
+    fn new(gaps: &[usize], device_offset: usize, copies: usize) -> ChainModel {
+        ChainModel {
+            gaps: gaps.iter().copied().collect(),
+            device_offset,
+            copies,
+        }
+    }
+
+    /// Every link's joltage from the outlet to the device, using each copy of
+    /// each adapter once
+    fn full_chain(&self, adapters: &[usize]) -> Vec<usize> {
+        let mut joltages = vec![0];
+        for &adapter in adapters {
+            joltages.extend(std::iter::repeat_n(adapter, self.copies));
+        }
+        joltages[1..].sort_unstable();
+
+        let device = joltages.last().unwrap() + self.device_offset;
+        joltages.push(device);
+
+        joltages
+    }
+
+    /// How many times each gap appears in the chain that uses every adapter,
+    /// including the step up to the device
+    fn gap_histogram(&self, adapters: &[usize]) -> Result<BTreeMap<usize, usize>, ChainError> {
+        let mut histogram = BTreeMap::new();
+
+        for pair in self.full_chain(adapters).windows(2) {
+            let gap = pair[1] - pair[0];
+            if !self.gaps.contains(&gap) {
+                return Err(ChainError::GapNotAllowed {
+                    from: pair[0],
+                    to: pair[1],
+                });
+            }
+            *histogram.entry(gap).or_insert(0) += 1;
+        }
+
+        Ok(histogram)
+    }
+
+    /// How many arrangements reach the device
+    fn count(&self, adapters: &[usize]) -> Result<u128, ChainError> {
+        match AdapterTable::with_model(adapters, self).count() {
+            Some(0) => Err(ChainError::NoChain),
+            Some(count) => Ok(count),
+            None => Err(ChainError::Overflow),
+        }
+    }
+}
+
+/// Every link a chain could use, from the outlet through each adapter copy
+/// to the device, along with how many ways each link can reach the device
+struct AdapterTable {
+    /// Link joltages in ascending order. The outlet is first and the device
+    /// is last.
+    joltages: Vec<usize>,
+    /// Links that can plug straight into each link, by ascending joltage.
+    /// A joltage is always entered at its first copy, so copies are only
+    /// ever used in order and identical arrangements aren't repeated.
+    successors: Vec<Vec<usize>>,
+    /// Arrangements from each link up to the device. `None` means the count
+    /// doesn't fit in a `u128`, and 0 means the device can't be reached.
+    tracker: Vec<Option<u128>>,
+}
+
+impl AdapterTable {
+    fn new(adapters: &[usize]) -> AdapterTable {
+        AdapterTable::with_model(adapters, &ChainModel::default())
+    }
+
+    fn with_model(adapters: &[usize], model: &ChainModel) -> AdapterTable {
+        let joltages = model.full_chain(adapters);
+        let device = joltages.len() - 1;
+        let device_joltage = joltages[device];
+
+        let mut first_copies: BTreeMap<usize, usize> = BTreeMap::new();
+        for (link, &joltage) in joltages.iter().enumerate().take(device).skip(1) {
+            first_copies.entry(joltage).or_insert(link);
+        }
+
+        let successors: Vec<Vec<usize>> = (0..=device)
+            .map(|link| {
+                if link == device {
+                    return vec![];
+                }
+                let joltage = joltages[link];
+                let mut next = vec![];
+
+                if device_joltage >= joltage && model.gaps.contains(&(device_joltage - joltage)) {
+                    next.push(device);
+                }
+                for &gap in &model.gaps {
+                    let target = if gap == 0 {
+                        Some(link + 1).filter(|&copy| copy < device && joltages[copy] == joltage)
+                    } else {
+                        first_copies.get(&(joltage + gap)).copied()
+                    };
+                    next.extend(target);
+                }
+
+                // The device sorts first, since ending the chain there gives
+                // a prefix of every way of continuing it
+                next.sort_unstable_by_key(|&n| (n != device, joltages[n]));
+                next
+            })
+            .collect();
+
+        let mut tracker = vec![Some(0); device + 1];
+        tracker[device] = Some(1);
+        for link in (0..device).rev() {
+            tracker[link] = successors[link].iter().try_fold(0u128, |sum, &next| {
+                tracker[next].and_then(|count| sum.checked_add(count))
+            });
+        }
 
-    for x in adapts.into_iter().rev() {
-        let slice = &tracker[x + 1..=x + 3];
-        tracker[x] = slice.iter().sum();
+        AdapterTable {
+            joltages,
+            successors,
+            tracker,
+        }
     }
 
-    tracker[1..=3].iter().sum()
+    /// Every arrangement from the outlet to the device, or `None` if there are
+    /// more than `u128::MAX`
+    fn count(&self) -> Option<u128> {
+        self.tracker[0]
+    }
+
+    fn device(&self) -> usize {
+        self.joltages.len() - 1
+    }
+
+    fn reaches_device(&self, link: usize) -> bool {
+        self.tracker[link] != Some(0)
+    }
+
+    /// Joltages of the adapters in a chain of links
+    fn to_joltages(&self, chain: &[usize]) -> Vec<usize> {
+        chain.iter().map(|&link| self.joltages[link]).collect()
+    }
+
+    /// Every arrangement in lexicographic order of their joltages, so each
+    /// one comes before any that extend it, generated as they're needed
+    fn arrangements(&self) -> Arrangements<'_> {
+        Arrangements {
+            table: self,
+            chain: vec![],
+            started: false,
+        }
+    }
+
+    /// A uniformly random arrangement, picking each next adapter in proportion
+    /// to how many arrangements continue through it. `None` if there are no
+    /// arrangements or too many to count.
+    fn sample(&self, rng: &mut SplitMix64) -> Option<Vec<usize>> {
+        let mut remaining = self.count().filter(|&count| count > 0)?;
+        let mut chain = vec![];
+        let mut link = 0;
+
+        while link != self.device() {
+            let mut pick = rng.below(remaining);
+            for &next in &self.successors[link] {
+                let count = self.tracker[next]?;
+                if pick < count {
+                    link = next;
+                    remaining = count;
+                    break;
+                }
+                pick -= count;
+            }
+
+            if link != self.device() {
+                chain.push(link);
+            }
+        }
+
+        Some(self.to_joltages(&chain))
+    }
+}
+
+/// Walks the arrangements depth first, always trying the smallest adapter
+/// next. Links that can't reach the device are skipped, so every step leads
+/// to an arrangement.
+struct Arrangements<'a> {
+    table: &'a AdapterTable,
+    /// The links in the last arrangement returned
+    chain: Vec<usize>,
+    started: bool,
+}
+
+impl<'a> Arrangements<'a> {
+    /// Extends the chain with the smallest adapters until it reaches the device
+    fn descend(&mut self) {
+        let table = self.table;
+        let mut link = self.chain.last().copied().unwrap_or(0);
+
+        loop {
+            let next = table.successors[link]
+                .iter()
+                .copied()
+                .find(|&next| table.reaches_device(next))
+                .unwrap();
+            if next == table.device() {
+                return;
+            }
+            self.chain.push(next);
+            link = next;
+        }
+    }
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let table = self.table;
+        if !self.started {
+            self.started = true;
+            if !table.reaches_device(0) {
+                return None;
+            }
+            self.descend();
+            return Some(table.to_joltages(&self.chain));
+        }
+
+        // Swap the deepest link that has a larger sibling, then take the
+        // smallest adapters from there on
+        let mut last = table.device();
+        loop {
+            let parent = self.chain.last().copied().unwrap_or(0);
+            let siblings = &table.successors[parent];
+            let after = siblings.iter().position(|&link| link == last).unwrap() + 1;
+            let sibling = siblings[after..]
+                .iter()
+                .copied()
+                .find(|&link| table.reaches_device(link));
+
+            if let Some(sibling) = sibling {
+                if sibling != table.device() {
+                    self.chain.push(sibling);
+                    self.descend();
+                }
+                return Some(table.to_joltages(&self.chain));
+            }
+
+            last = self.chain.pop()?;
+        }
+    }
+}
+
+/// A small, fast pseudorandom generator, good enough for sampling
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+        z ^ (z >> 31)
+    }
+
+    /// A uniform value below `bound`, rejecting draws from the uneven top end
+    fn below(&mut self, bound: u128) -> u128 {
+        assert!(bound > 0, "bound must be positive");
+        let zone = u128::MAX - u128::MAX % bound;
+
+        loop {
+            let draw = (self.next_u64() as u128) << 64 | self.next_u64() as u128;
+            if draw < zone {
+                return draw % bound;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -81,7 +381,7 @@ mod tests {
 
     #[test]
     fn product_jolt_differences_example_1() {
-        let expected = 35;
+        let expected = Ok(35);
         let example_adapters = EXAMPLE_ADAPTER_1
             .lines()
             .map(|l| l.parse().unwrap())
@@ -93,7 +393,7 @@ mod tests {
 
     #[test]
     fn product_jolt_differences_example_2() {
-        let expected = 220;
+        let expected = Ok(220);
         let example_adapters = EXAMPLE_ADAPTER_2
             .lines()
             .map(|l| l.parse().unwrap())
@@ -108,7 +408,7 @@ mod tests {
         let expected = 2277;
 
         let adapters = load_as_vec_usize("day10");
-        let actual = product_jolt_differences(adapters);
+        let actual = product_jolt_differences(adapters).unwrap();
         println!(
             "{}{}",
             "Product of 1 jolt and 3 jolt differences: ".green().bold(),
@@ -118,6 +418,36 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn gap_histogram_example_2() {
+        let example_adapters: Vec<usize> = EXAMPLE_ADAPTER_2
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let expected: BTreeMap<usize, usize> = vec![(1, 22), (3, 10)].into_iter().collect();
+        let actual = ChainModel::default().gap_histogram(&example_adapters);
+
+        assert_eq!(actual, Ok(expected));
+    }
+
+    #[test]
+    fn gap_histogram_errors() {
+        assert_eq!(
+            product_jolt_differences(vec![1, 2, 6, 7]),
+            Err(ChainError::GapNotAllowed { from: 2, to: 6 })
+        );
+        assert_eq!(
+            ChainModel::new(&[1, 3], 3, 2).gap_histogram(&[1, 4]),
+            Err(ChainError::GapNotAllowed { from: 1, to: 1 })
+        );
+
+        let expected: BTreeMap<usize, usize> =
+            vec![(0, 2), (1, 1), (3, 1), (5, 1)].into_iter().collect();
+        let actual = ChainModel::new(&[0, 1, 3, 5], 5, 2).gap_histogram(&[1, 4]);
+
+        assert_eq!(actual, Ok(expected));
+    }
+
     // Part2
     #[test]
     fn total_distinct_arrangements_example_1() {
@@ -143,6 +473,134 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn arrangements_example_1() {
+        let example_adapters: Vec<usize> = EXAMPLE_ADAPTER_1
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let table = AdapterTable::new(&example_adapters);
+        let arrangements: Vec<Vec<usize>> = table.arrangements().collect();
+
+        assert_eq!(arrangements.len(), 8);
+        assert_eq!(arrangements[0], vec![1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19]);
+        assert_eq!(arrangements[7], vec![1, 4, 7, 10, 12, 15, 16, 19]);
+        assert!(arrangements.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn arrangements_match_count() {
+        let example_adapters: Vec<usize> = EXAMPLE_ADAPTER_2
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let table = AdapterTable::new(&example_adapters);
+
+        assert_eq!(table.arrangements().count(), 19208);
+        assert_eq!(AdapterTable::new(&[]).arrangements().count(), 1);
+        assert_eq!(AdapterTable::new(&[1, 5]).arrangements().count(), 0);
+    }
+
+    #[test]
+    fn sample_is_valid_and_covers_arrangements() {
+        let example_adapters: Vec<usize> = EXAMPLE_ADAPTER_1
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let table = AdapterTable::new(&example_adapters);
+        let all: Vec<Vec<usize>> = table.arrangements().collect();
+        let mut rng = SplitMix64::new(2020);
+        let mut seen = vec![0; all.len()];
+
+        for _ in 0..800 {
+            let sample = table.sample(&mut rng).unwrap();
+            seen[all.iter().position(|a| *a == sample).unwrap()] += 1;
+        }
+
+        assert!(seen.iter().all(|&times| times > 50), "{:?}", seen);
+        assert_eq!(AdapterTable::new(&[1, 5]).sample(&mut rng), None);
+    }
+
+    #[test]
+    fn count_long_chain() {
+        // Every joltage is an adapter, so the counts are tribonacci numbers
+        let adapters: Vec<usize> = (1..=146).collect();
+        let long_adapters: Vec<usize> = (1..=147).collect();
+
+        assert_eq!(
+            AdapterTable::new(&adapters).count(),
+            Some(269216838928454720696964324302545675954)
+        );
+        assert_eq!(AdapterTable::new(&long_adapters).count(), None);
+    }
+
+    #[test]
+    fn chain_model_counts() {
+        // Only steps of 2 and 4, so odd adapters are never usable
+        let model = ChainModel::new(&[2, 4], 4, 1);
+        let arrangements: Vec<Vec<usize>> = AdapterTable::with_model(&[2, 3, 4, 6], &model)
+            .arrangements()
+            .collect();
+
+        assert_eq!(model.count(&[2, 3, 4, 6]), Ok(arrangements.len() as u128));
+        assert_eq!(arrangements, vec![vec![2, 4, 6], vec![2, 6], vec![4, 6],]);
+        assert_eq!(model.count(&[3]), Err(ChainError::NoChain));
+        assert_eq!(
+            ChainModel::default().count(&(1..=147).collect::<Vec<usize>>()),
+            Err(ChainError::Overflow)
+        );
+    }
+
+    #[test]
+    fn chain_model_copies() {
+        let model = ChainModel::new(&[0, 1, 2, 3], 3, 2);
+        let arrangements: Vec<Vec<usize>> = AdapterTable::with_model(&[1, 2], &model)
+            .arrangements()
+            .collect();
+
+        // 1 can be skipped, used once or used twice, but 2 is needed to
+        // reach the device
+        assert_eq!(model.count(&[1, 2]), Ok(6));
+        assert_eq!(
+            arrangements,
+            vec![
+                vec![1, 1, 2],
+                vec![1, 1, 2, 2],
+                vec![1, 2],
+                vec![1, 2, 2],
+                vec![2],
+                vec![2, 2],
+            ]
+        );
+        assert_eq!(
+            ChainModel::new(&[1, 2, 3], 3, 2).count(&[1, 2]),
+            ChainModel::default().count(&[1, 2])
+        );
+    }
+
+    #[test]
+    fn arrangements_prefixes_come_first() {
+        // With the device 1 jolt up, every adapter can end the chain
+        let model = ChainModel::new(&[1, 2, 3], 1, 1);
+        let arrangements: Vec<Vec<usize>> = AdapterTable::with_model(&[1, 2, 3], &model)
+            .arrangements()
+            .collect();
+
+        assert_eq!(
+            arrangements,
+            vec![
+                vec![1],
+                vec![1, 2],
+                vec![1, 2, 3],
+                vec![1, 3],
+                vec![2],
+                vec![2, 3],
+                vec![3],
+            ]
+        );
+        assert!(arrangements.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
     #[test]
     fn total_distinct_arrangements_from_input() {
         let expected = 37024595836928;
@@ -1,89 +1,825 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
 
 fn count_bags_hold_shiny_gold(rules: Vec<String>) -> usize {
-    let parsed_rules = parse_rules(rules);
+    let graph = BagGraph::from_rules(&parse_rules(rules));
 
-    find_bags_that_hold("shiny gold".to_string(), &parsed_rules).len()
+    graph
+        .id("shiny gold")
+        .map_or(0, |bag| graph.ancestors(bag).len())
 }
 
-fn find_bags_that_hold(
-    desired_bag: String,
+/// Part2
+fn total_bags_inside_shiny_gold(rules: Vec<String>) -> Result<u128, BagError> {
+    let graph = BagGraph::from_rules(&parse_rules(rules));
+    let bag = graph
+        .id("shiny gold")
+        .ok_or_else(|| BagError::UnknownBag("shiny gold".to_string()))?;
+
+    graph.total_inside(bag)
+}
+
+/// Index of a bag's color in a `BagGraph`
+type BagId = usize;
+
+/// The luggage rules with every color interned as a `BagId`, so walking the
+/// rules in either direction is a lookup instead of a scan
+#[derive(PartialEq, Debug, Clone)]
+struct BagGraph {
+    names: Vec<String>,
+    ids: HashMap<String, BagId>,
+    /// The bags each bag directly holds, and how many of each
+    contents: Vec<Vec<(BagId, usize)>>,
+    /// The bags that directly hold each bag
+    holders: Vec<Vec<BagId>>,
+}
+
+#[derive(PartialEq, Debug)]
+enum BagError {
+    UnknownBag(String),
+    /// The bags, in containment order, of a loop where a bag ends up inside
+    /// itself. Each one holds the next, and the last holds the first.
+    Cycle(Vec<String>),
+    /// More than `u128::MAX` bags
+    Overflow,
+}
+
+impl BagGraph {
+    /// Interns every color in sorted order, so ids don't depend on hashing
+    fn from_rules(rules: &HashMap<String, HashMap<String, usize>>) -> BagGraph {
+        let mut names: Vec<String> = rules
+            .iter()
+            .flat_map(|(outer, inner)| std::iter::once(outer).chain(inner.keys()))
+            .cloned()
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let ids: HashMap<String, BagId> = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id))
+            .collect();
+
+        let mut contents = vec![vec![]; names.len()];
+        let mut holders = vec![vec![]; names.len()];
+        for (outer, inner) in rules {
+            let outer = ids[outer];
+            for (bag, &count) in inner {
+                contents[outer].push((ids[bag], count));
+                holders[ids[bag]].push(outer);
+            }
+        }
+        for list in contents.iter_mut() {
+            list.sort_unstable();
+        }
+        for list in holders.iter_mut() {
+            list.sort_unstable();
+        }
+
+        BagGraph {
+            names,
+            ids,
+            contents,
+            holders,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    fn id(&self, name: &str) -> Option<BagId> {
+        self.ids.get(name).copied()
+    }
+
+    fn name(&self, bag: BagId) -> &str {
+        &self.names[bag]
+    }
+
+    fn contents(&self, bag: BagId) -> &[(BagId, usize)] {
+        &self.contents[bag]
+    }
+
+    fn holders(&self, bag: BagId) -> &[BagId] {
+        &self.holders[bag]
+    }
+
+    /// Every bag that eventually holds `bag`, sorted by id
+    fn ancestors(&self, bag: BagId) -> Vec<BagId> {
+        self.reachable(bag, |b| self.holders[b].to_vec())
+    }
+
+    /// Every bag that `bag` eventually holds, sorted by id
+    fn descendants(&self, bag: BagId) -> Vec<BagId> {
+        self.reachable(bag, |b| {
+            self.contents[b].iter().map(|&(inner, _)| inner).collect()
+        })
+    }
+
+    /// Breadth first search from `start`, which is only included if it can
+    /// reach itself
+    fn reachable<F>(&self, start: BagId, next: F) -> Vec<BagId>
+    where
+        F: Fn(BagId) -> Vec<BagId>,
+    {
+        let mut seen = vec![false; self.len()];
+        let mut queue: VecDeque<BagId> = next(start).into_iter().collect();
+
+        while let Some(bag) = queue.pop_front() {
+            if !seen[bag] {
+                seen[bag] = true;
+                queue.extend(next(bag));
+            }
+        }
+
+        (0..self.len()).filter(|&bag| seen[bag]).collect()
+    }
+
+    /// Bags one step from `bag`, following `direction`
+    fn neighbors(&self, bag: BagId, direction: Direction) -> Vec<BagId> {
+        match direction {
+            Direction::Contents => self.contents[bag].iter().map(|&(inner, _)| inner).collect(),
+            Direction::Holders => self.holders[bag].to_vec(),
+        }
+    }
+
+    /// How many steps each bag is from the nearest of `starts`, following
+    /// `direction` and stopping after `max_depth` steps
+    fn depths(
+        &self,
+        starts: &[BagId],
+        direction: Direction,
+        max_depth: Option<usize>,
+    ) -> Vec<Option<usize>> {
+        let mut depths = vec![None; self.len()];
+        let mut queue = VecDeque::new();
+        for &start in starts {
+            depths[start] = Some(0);
+            queue.push_back(start);
+        }
+
+        while let Some(bag) = queue.pop_front() {
+            let depth = depths[bag].unwrap() + 1;
+            if max_depth.is_some_and(|max| depth > max) {
+                continue;
+            }
+            for next in self.neighbors(bag, direction) {
+                if depths[next].is_none() {
+                    depths[next] = Some(depth);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        depths
+    }
+
+    /// The fewest bags to go through to get from `from` down to `to`,
+    /// including both ends, or `None` if `from` never holds `to`
+    fn shortest_path(&self, from: BagId, to: BagId) -> Option<Vec<BagId>> {
+        let mut parents: Vec<Option<BagId>> = vec![None; self.len()];
+        let mut queue = VecDeque::from(vec![from]);
+        let mut found = from == to;
+
+        while let Some(bag) = queue.pop_front() {
+            if found {
+                break;
+            }
+            for &(inner, _) in &self.contents[bag] {
+                if inner != from && parents[inner].is_none() {
+                    parents[inner] = Some(bag);
+                    queue.push_back(inner);
+                    found |= inner == to;
+                }
+            }
+        }
+        if !found {
+            return None;
+        }
+
+        let mut path = vec![to];
+        while let Some(parent) = parents[*path.last().unwrap()] {
+            path.push(parent);
+        }
+        path.reverse();
+
+        Some(path)
+    }
+
+    /// How many bags `bag` holds in total
+    fn total_inside(&self, bag: BagId) -> Result<u128, BagError> {
+        let mut memo = vec![None; self.len()];
+        let mut path = vec![];
+
+        self.count_inside(bag, &|_| true, &mut memo, &mut path)
+    }
+
+    /// How many bags each bag holds in total, indexed by id
+    fn all_totals(&self) -> Result<Vec<u128>, BagError> {
+        self.count_all(&|_| true)
+    }
+
+    /// How many `target` bags each bag holds, directly or not, indexed by id
+    fn all_copies_of(&self, target: BagId) -> Result<Vec<u128>, BagError> {
+        self.count_all(&|bag| bag == target)
+    }
+
+    fn count_all(&self, counted: &dyn Fn(BagId) -> bool) -> Result<Vec<u128>, BagError> {
+        let mut memo = vec![None; self.len()];
+        let mut path = vec![];
+        for bag in 0..self.len() {
+            self.count_inside(bag, counted, &mut memo, &mut path)?;
+        }
+
+        Ok(memo.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// Depth first count of the bags inside `bag` that are `counted`. Each
+    /// bag's count is remembered, so shared bags are only counted once.
+    /// `path` holds the bags being counted, so finding one of them again
+    /// means a cycle.
+    fn count_inside(
+        &self,
+        bag: BagId,
+        counted: &dyn Fn(BagId) -> bool,
+        memo: &mut Vec<Option<u128>>,
+        path: &mut Vec<BagId>,
+    ) -> Result<u128, BagError> {
+        if let Some(total) = memo[bag] {
+            return Ok(total);
+        }
+        if let Some(start) = path.iter().position(|&b| b == bag) {
+            let cycle = path[start..]
+                .iter()
+                .map(|&b| self.names[b].clone())
+                .collect();
+            return Err(BagError::Cycle(cycle));
+        }
+
+        path.push(bag);
+        let mut total: u128 = 0;
+        for &(inner, count) in &self.contents[bag] {
+            let inside = self.count_inside(inner, counted, memo, path)?;
+            total = inside
+                .checked_add(counted(inner) as u128)
+                .and_then(|each| each.checked_mul(count as u128))
+                .and_then(|bags| bags.checked_add(total))
+                .ok_or(BagError::Overflow)?;
+        }
+        path.pop();
+
+        memo[bag] = Some(total);
+        Ok(total)
+    }
+}
+
+/// Which way to walk the rules from a bag
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Direction {
+    /// Towards the bags it holds
+    Contents,
+    /// Towards the bags that hold it
+    Holders,
+}
+
+/// The bag to highlight in a DOT export, along with every bag reachable from
+/// it in `direction`
+#[derive(PartialEq, Debug, Clone)]
+struct DotFocus {
+    bag: String,
+    direction: Direction,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+struct DotOptions {
+    focus: Option<DotFocus>,
+    /// Leaves out bags more than this many steps from the focus, or from the
+    /// outermost bags when there's no focus
+    max_depth: Option<usize>,
+}
+
+/// Writes the rules as a Graphviz digraph, with an edge from each bag to the
+/// bags it holds labeled with how many
+fn rules_to_dot(
     rules: &HashMap<String, HashMap<String, usize>>,
-) -> HashSet<String> {
-    let bags_containing: Vec<String> = rules
-        .iter()
-        .filter(|(_, v)| v.contains_key(&desired_bag))
-        .map(|(k, _)| k.to_owned())
-        .collect();
+    options: &DotOptions,
+) -> Result<String, BagError> {
+    let graph = BagGraph::from_rules(rules);
 
-    bags_containing
-        .iter()
-        .fold(bags_containing.clone(), |a, bag| {
-            find_bags_that_hold(bag.to_owned(), rules)
+    let highlighted = match &options.focus {
+        Some(focus) => {
+            let bag = graph
+                .id(&focus.bag)
+                .ok_or_else(|| BagError::UnknownBag(focus.bag.clone()))?;
+            Some((
+                bag,
+                graph.depths(&[bag], focus.direction, options.max_depth),
+            ))
+        }
+        None => None,
+    };
+    let included: Vec<bool> = match (&highlighted, options.max_depth) {
+        (Some((_, depths)), Some(_)) => depths.iter().map(Option::is_some).collect(),
+        (None, Some(max_depth)) => {
+            let outermost: Vec<BagId> = (0..graph.len())
+                .filter(|&bag| graph.holders(bag).is_empty())
+                .collect();
+            graph
+                .depths(&outermost, Direction::Contents, Some(max_depth))
                 .iter()
-                .chain(a.iter())
-                .map(|s| s.to_owned())
+                .map(Option::is_some)
                 .collect()
-        })
-        .iter()
-        .map(|s| s.to_owned())
-        .collect()
+        }
+        (_, None) => vec![true; graph.len()],
+    };
+    let is_highlighted = |bag: BagId| {
+        highlighted
+            .as_ref()
+            .is_some_and(|(_, depths)| depths[bag].is_some())
+    };
+
+    let mut lines = vec![
+        "digraph bags {".to_string(),
+        "  node [shape=box];".to_string(),
+    ];
+    for bag in (0..graph.len()).filter(|&bag| included[bag]) {
+        let style = match &highlighted {
+            Some((focus, _)) if *focus == bag => " [style=filled, fillcolor=gold]",
+            _ if is_highlighted(bag) => " [style=filled, fillcolor=lightblue]",
+            _ => "",
+        };
+        lines.push(format!("  {}{};", dot_id(graph.name(bag)), style));
+    }
+    for outer in (0..graph.len()).filter(|&bag| included[bag]) {
+        for &(inner, count) in graph.contents(outer) {
+            if !included[inner] {
+                continue;
+            }
+            let style = if is_highlighted(outer) && is_highlighted(inner) {
+                ", color=blue, penwidth=2"
+            } else {
+                ""
+            };
+            lines.push(format!(
+                "  {} -> {} [label=\"{}\"{}];",
+                dot_id(graph.name(outer)),
+                dot_id(graph.name(inner)),
+                count,
+                style
+            ));
+        }
+    }
+    lines.push("}".to_string());
+
+    Ok(lines.join("\n"))
 }
 
-/// Part2
-fn total_bags_inside_shiny_gold(rules: Vec<String>) -> usize {
-    let parsed_rules = parse_rules(rules);
+/// Quotes a bag's color so it's a valid DOT id
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
 
-    total_bags_inside("shiny gold".to_string(), &parsed_rules)
+/// A question about the luggage rules. Bag colors can be any number of
+/// words. Written as one of:
+///
+/// - `leaves`: bags that hold no other bags
+/// - `roots`: bags that no other bag holds
+/// - `holders <bag>`: bags that eventually hold `<bag>`
+/// - `contents <bag>`: bags that `<bag>` eventually holds
+/// - `holding <n> <bag>`: bags holding at least `n` of `<bag>`, counting
+///   the ones inside other bags
+/// - `path <bag> -> <bag>`: the shortest chain of bags from the first down
+///   to the second
+/// - `total <bag>`: how many bags `<bag>` holds in total
+/// - `count <query>`: how many bags a list query found
+#[derive(PartialEq, Debug, Clone)]
+enum Query {
+    Leaves,
+    Roots,
+    Holders(String),
+    Contents(String),
+    Holding { at_least: u128, bag: String },
+    Path { from: String, to: String },
+    Total(String),
+    Count(Box<Query>),
+}
+
+#[derive(PartialEq, Debug)]
+enum QueryResult {
+    Bags(Vec<String>),
+    Count(u128),
+}
+
+#[derive(PartialEq, Debug)]
+enum QueryError {
+    Empty,
+    UnknownCommand(String),
+    /// The command needs a bag color after it
+    MissingBag(&'static str),
+    InvalidNumber(String),
+    /// `path` needs two colors separated by `->`
+    MissingArrow,
+    /// `count` was given a query that doesn't find a list of bags
+    NotAList,
+    Bag(BagError),
+}
+
+impl From<BagError> for QueryError {
+    fn from(err: BagError) -> Self {
+        QueryError::Bag(err)
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let (&command, args) = words.split_first().ok_or(QueryError::Empty)?;
+        let bag = |args: &[&str], command: &'static str| {
+            if args.is_empty() {
+                Err(QueryError::MissingBag(command))
+            } else {
+                Ok(args.join(" "))
+            }
+        };
+
+        match command {
+            "leaves" => Ok(Query::Leaves),
+            "roots" => Ok(Query::Roots),
+            "holders" => Ok(Query::Holders(bag(args, "holders")?)),
+            "contents" => Ok(Query::Contents(bag(args, "contents")?)),
+            "total" => Ok(Query::Total(bag(args, "total")?)),
+            "holding" => {
+                let (&raw_n, rest) = args
+                    .split_first()
+                    .ok_or(QueryError::MissingBag("holding"))?;
+                let at_least = raw_n
+                    .parse()
+                    .map_err(|_| QueryError::InvalidNumber(raw_n.to_string()))?;
+
+                Ok(Query::Holding {
+                    at_least,
+                    bag: bag(rest, "holding")?,
+                })
+            }
+            "path" => {
+                let arrow = args
+                    .iter()
+                    .position(|&word| word == "->")
+                    .ok_or(QueryError::MissingArrow)?;
+
+                Ok(Query::Path {
+                    from: bag(&args[..arrow], "path")?,
+                    to: bag(&args[arrow + 1..], "path")?,
+                })
+            }
+            "count" => Ok(Query::Count(Box::new(args.join(" ").parse()?))),
+            _ => Err(QueryError::UnknownCommand(command.to_string())),
+        }
+    }
 }
 
-fn total_bags_inside(
-    desired_bag: String,
+impl Query {
+    fn evaluate(&self, graph: &BagGraph) -> Result<QueryResult, QueryError> {
+        let id = |name: &str| {
+            graph
+                .id(name)
+                .ok_or_else(|| BagError::UnknownBag(name.to_string()))
+        };
+        let names = |bags: Vec<BagId>| {
+            QueryResult::Bags(
+                bags.into_iter()
+                    .map(|bag| graph.name(bag).to_string())
+                    .collect(),
+            )
+        };
+
+        match self {
+            Query::Leaves => Ok(names(
+                (0..graph.len())
+                    .filter(|&bag| graph.contents(bag).is_empty())
+                    .collect(),
+            )),
+            Query::Roots => Ok(names(
+                (0..graph.len())
+                    .filter(|&bag| graph.holders(bag).is_empty())
+                    .collect(),
+            )),
+            Query::Holders(bag) => Ok(names(graph.ancestors(id(bag)?))),
+            Query::Contents(bag) => Ok(names(graph.descendants(id(bag)?))),
+            Query::Holding { at_least, bag } => {
+                let copies = graph.all_copies_of(id(bag)?)?;
+
+                Ok(names(
+                    (0..graph.len())
+                        .filter(|&holder| copies[holder] >= *at_least && copies[holder] > 0)
+                        .collect(),
+                ))
+            }
+            Query::Path { from, to } => Ok(names(
+                graph.shortest_path(id(from)?, id(to)?).unwrap_or_default(),
+            )),
+            Query::Total(bag) => Ok(QueryResult::Count(graph.total_inside(id(bag)?)?)),
+            Query::Count(query) => match query.evaluate(graph)? {
+                QueryResult::Bags(bags) => Ok(QueryResult::Count(bags.len() as u128)),
+                QueryResult::Count(_) => Err(QueryError::NotAList),
+            },
+        }
+    }
+}
+
+/// Lists one bag per line
+impl fmt::Display for QueryResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryResult::Bags(bags) if bags.is_empty() => write!(f, "no bags"),
+            QueryResult::Bags(bags) => write!(f, "{}", bags.join("\n")),
+            QueryResult::Count(count) => write!(f, "{}", count),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::Empty => write!(f, "empty query"),
+            QueryError::UnknownCommand(command) => write!(f, "unknown command `{}`", command),
+            QueryError::MissingBag(command) => write!(f, "`{}` needs a bag color", command),
+            QueryError::InvalidNumber(raw) => write!(f, "`{}` isn't a number", raw),
+            QueryError::MissingArrow => write!(f, "`path` needs `<bag> -> <bag>`"),
+            QueryError::NotAList => write!(f, "`count` needs a query that lists bags"),
+            QueryError::Bag(BagError::UnknownBag(bag)) => write!(f, "no rule mentions `{}`", bag),
+            QueryError::Bag(BagError::Cycle(bags)) => {
+                write!(f, "bags hold themselves: {}", bags.join(" -> "))
+            }
+            QueryError::Bag(BagError::Overflow) => write!(f, "more than u128::MAX bags"),
+        }
+    }
+}
+
+/// Parses and answers one query
+fn query_rules(
     rules: &HashMap<String, HashMap<String, usize>>,
-) -> usize {
-    let bags_inside: &HashMap<String, usize> = rules.get_key_value(&desired_bag).unwrap().1;
+    query: &str,
+) -> Result<QueryResult, QueryError> {
+    query
+        .parse::<Query>()?
+        .evaluate(&BagGraph::from_rules(rules))
+}
 
-    bags_inside
-        .iter()
-        .map(|(b, n)| n + n * total_bags_inside(b.to_owned(), rules))
-        .sum()
+/// Answers a query per line of `input` until it runs out or reads `quit`
+fn run_query_repl<R: BufRead, W: Write>(
+    graph: &BagGraph,
+    input: R,
+    output: &mut W,
+) -> io::Result<()> {
+    write!(output, "> ")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        match line.trim() {
+            "" => {}
+            "quit" | "exit" => break,
+            query => match query.parse::<Query>().and_then(|q| q.evaluate(graph)) {
+                Ok(result) => writeln!(output, "{}", result)?,
+                Err(err) => writeln!(output, "error: {}", err)?,
+            },
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+
+    Ok(())
 }
 
+/// Parses trusted puzzle input, panicking with the error's position if a
+/// rule is malformed
 fn parse_rules(rules: Vec<String>) -> HashMap<String, HashMap<String, usize>> {
-    rules
+    parse_rule_text(&rules.join("\n")).unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[derive(PartialEq, Debug)]
+struct RuleParseError {
+    /// Counting from 1
+    line: usize,
+    /// The character the problem starts at, counting from 1
+    column: usize,
+    kind: RuleErrorKind,
+}
+
+#[derive(PartialEq, Debug)]
+enum RuleErrorKind {
+    /// The line has no `contain` between the outer bag and its contents
+    MissingContain,
+    /// A bag was described with no color before `bag` or `bags`
+    MissingColor,
+    /// A bag description ended with this instead of `bag` or `bags`
+    ExpectedBagWord(String),
+    /// A content didn't start with a number
+    InvalidCount(String),
+    /// Punctuation somewhere a color or count should be
+    UnexpectedToken(String),
+    /// Nothing after `contain`, or between two commas
+    MissingContents,
+    /// The same color is listed twice in one bag's contents
+    DuplicateContent(String),
+    /// A second rule for a bag that already has one
+    DuplicateRule(String),
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+
+        match &self.kind {
+            RuleErrorKind::MissingContain => write!(f, "expected `contain`"),
+            RuleErrorKind::MissingColor => write!(f, "expected a bag color"),
+            RuleErrorKind::ExpectedBagWord(found) => {
+                write!(f, "expected `bag` or `bags`, found `{}`", found)
+            }
+            RuleErrorKind::InvalidCount(found) => write!(f, "expected a number, found `{}`", found),
+            RuleErrorKind::UnexpectedToken(found) => write!(f, "unexpected `{}`", found),
+            RuleErrorKind::MissingContents => write!(f, "expected bag contents"),
+            RuleErrorKind::DuplicateContent(bag) => write!(f, "`{}` is listed twice", bag),
+            RuleErrorKind::DuplicateRule(bag) => write!(f, "`{}` already has a rule", bag),
+        }
+    }
+}
+
+/// Byte offset and text of a word or punctuation mark in a rule
+type Token<'a> = (usize, &'a str);
+
+/// Parses one rule per line, skipping blank lines. Accepts `bag` or `bags`
+/// anywhere, any amount of whitespace, colors of any number of words and
+/// an optional trailing period.
+fn parse_rule_text(text: &str) -> Result<HashMap<String, HashMap<String, usize>>, RuleParseError> {
+    let mut rules = HashMap::new();
+
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = |offset: usize, kind: RuleErrorKind| RuleParseError {
+            line: i + 1,
+            column: line[..offset].chars().count() + 1,
+            kind,
+        };
+
+        let (bag, contents) =
+            parse_rule_line(line).map_err(|(offset, kind)| error(offset, kind))?;
+        if rules.contains_key(&bag) {
+            let offset = line.len() - line.trim_start().len();
+            return Err(error(offset, RuleErrorKind::DuplicateRule(bag)));
+        }
+        rules.insert(bag, contents);
+    }
+
+    Ok(rules)
+}
+
+/// Parses a rule, or returns the byte offset of the problem with it
+fn parse_rule_line(line: &str) -> Result<(String, HashMap<String, usize>), (usize, RuleErrorKind)> {
+    let tokens = tokenize_rule(line);
+    let line_end = line.trim_end().len();
+
+    let contain = tokens
+        .iter()
+        .position(|&(_, token)| token == "contain")
+        .ok_or((line_end, RuleErrorKind::MissingContain))?;
+    let outer = parse_bag_words(&tokens[..contain], tokens[contain].0)?;
+
+    let mut rest = &tokens[contain + 1..];
+    let mut end = line_end;
+    if let Some((&(offset, "."), init)) = rest.split_last() {
+        rest = init;
+        end = offset;
+    }
+    if rest.is_empty() {
+        return Err((end, RuleErrorKind::MissingContents));
+    }
+
+    let words: Vec<&str> = rest.iter().map(|&(_, token)| token).collect();
+    if let ["no", "other", "bag"] | ["no", "other", "bags"] = words[..] {
+        return Ok((outer, HashMap::new()));
+    }
+
+    let mut contents = HashMap::new();
+    let mut item_start = 0;
+    for (i, &(offset, token)) in rest
         .iter()
-        .map(|l| parse_single_rule(l.to_string()))
-        .collect()
+        .enumerate()
+        .chain(std::iter::once((rest.len(), &(end, ","))))
+    {
+        if token != "," {
+            continue;
+        }
+
+        let item = &rest[item_start..i];
+        item_start = i + 1;
+        let (&(count_offset, raw_count), color) = item
+            .split_first()
+            .ok_or((offset, RuleErrorKind::MissingContents))?;
+        let count: usize = raw_count.parse().map_err(|_| {
+            (
+                count_offset,
+                RuleErrorKind::InvalidCount(raw_count.to_string()),
+            )
+        })?;
+
+        let bag = parse_bag_words(color, offset)?;
+        if contents.contains_key(&bag) {
+            return Err((count_offset, RuleErrorKind::DuplicateContent(bag)));
+        }
+        contents.insert(bag, count);
+    }
+
+    Ok((outer, contents))
 }
 
-fn parse_single_rule(rule: String) -> (String, HashMap<String, usize>) {
-    let (big_bag, contains) = rule.split_at(rule.find(" bag").unwrap());
-    let contents = contains.split("contain ").nth(1).unwrap().to_string();
+/// Reads a color followed by `bag` or `bags`. `end` is where the words stop,
+/// for errors when there aren't any.
+fn parse_bag_words(tokens: &[Token], end: usize) -> Result<String, (usize, RuleErrorKind)> {
+    let (&(bag_offset, bag_word), color) = tokens
+        .split_last()
+        .ok_or((end, RuleErrorKind::MissingColor))?;
+    if let Some(&(offset, token)) = tokens
+        .iter()
+        .find(|&&(_, token)| token == "," || token == ".")
+    {
+        return Err((offset, RuleErrorKind::UnexpectedToken(token.to_string())));
+    }
+    if bag_word != "bag" && bag_word != "bags" {
+        return Err((
+            bag_offset,
+            RuleErrorKind::ExpectedBagWord(bag_word.to_string()),
+        ));
+    }
+    if color.is_empty() {
+        return Err((bag_offset, RuleErrorKind::MissingColor));
+    }
 
-    (big_bag.to_string(), parse_bag_contents(contents))
+    Ok(color
+        .iter()
+        .map(|&(_, word)| word)
+        .collect::<Vec<&str>>()
+        .join(" "))
 }
 
-fn parse_bag_contents(contents: String) -> HashMap<String, usize> {
-    contents
-        .split(',')
-        .filter_map(|bag| {
-            if bag == "no other bags." {
-                None
+/// Splits a rule into words, with each comma and period as its own token
+fn tokenize_rule(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut word_start = None;
+
+    for (i, c) in line.char_indices() {
+        if c.is_whitespace() || c == ',' || c == '.' {
+            if let Some(start) = word_start.take() {
+                tokens.push((start, &line[start..i]));
+            }
+            if !c.is_whitespace() {
+                tokens.push((i, &line[i..i + 1]));
+            }
+        } else if word_start.is_none() {
+            word_start = Some(i);
+        }
+    }
+    if let Some(start) = word_start {
+        tokens.push((start, &line[start..]));
+    }
+
+    tokens
+}
+
+/// Writes each rule as an AoC sentence, sorted by bag so the output doesn't
+/// depend on hashing. `parse_rule_text` reads it back to the same rules.
+fn write_rules(rules: &HashMap<String, HashMap<String, usize>>) -> String {
+    let mut bags: Vec<&String> = rules.keys().collect();
+    bags.sort_unstable();
+
+    bags.into_iter()
+        .map(|bag| {
+            let mut contents: Vec<(&String, &usize)> = rules[bag].iter().collect();
+            contents.sort_unstable();
+            let contents: Vec<String> = contents
+                .into_iter()
+                .map(|(inner, &count)| match count {
+                    1 => format!("1 {} bag", inner),
+                    _ => format!("{} {} bags", count, inner),
+                })
+                .collect();
+
+            if contents.is_empty() {
+                format!("{} bags contain no other bags.", bag)
             } else {
-                let words: Vec<&str> = bag.split_ascii_whitespace().collect();
-                Some((
-                    words
-                        .iter()
-                        .skip(1)
-                        .filter(|w| !w.contains("bag"))
-                        .map(|w| w.to_string())
-                        .collect::<Vec<String>>()
-                        .join(" "),
-                    words[0].parse::<usize>().unwrap(),
-                ))
+                format!("{} bags contain {}.", bag, contents.join(", "))
             }
         })
-        .collect()
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
 static EXAMPLE_RULES: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
@@ -131,7 +867,7 @@ mod tests {
 
     #[test]
     fn total_bags_example_1() {
-        let expected = 32;
+        let expected = Ok(32);
         let rules = EXAMPLE_RULES.lines().map(|l| l.to_string()).collect();
         let actual = total_bags_inside_shiny_gold(rules);
 
@@ -140,7 +876,7 @@ mod tests {
 
     #[test]
     fn total_bags_example_2() {
-        let expected = 126;
+        let expected = Ok(126);
         let rules = "shiny gold bags contain 2 dark red bags.
 dark red bags contain 2 dark orange bags.
 dark orange bags contain 2 dark yellow bags.
@@ -156,13 +892,425 @@ dark violet bags contain no other bags."
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn bag_graph_queries() {
+        let rules = EXAMPLE_RULES.lines().map(|l| l.to_string()).collect();
+        let graph = BagGraph::from_rules(&parse_rules(rules));
+        let names = |bags: Vec<BagId>| -> Vec<&str> {
+            bags.into_iter().map(|bag| graph.name(bag)).collect()
+        };
+        let muted_yellow = graph.id("muted yellow").unwrap();
+
+        assert_eq!(graph.len(), 9);
+        assert_eq!(
+            names(graph.ancestors(muted_yellow)),
+            vec!["dark orange", "light red"]
+        );
+        assert_eq!(
+            names(graph.descendants(muted_yellow)),
+            vec![
+                "dark olive",
+                "dotted black",
+                "faded blue",
+                "shiny gold",
+                "vibrant plum"
+            ]
+        );
+        assert_eq!(
+            names(graph.holders(graph.id("faded blue").unwrap()).to_vec()).len(),
+            3
+        );
+        assert_eq!(graph.total_inside(graph.id("faded blue").unwrap()), Ok(0));
+        assert_eq!(graph.id("plaid purple"), None);
+    }
+
+    #[test]
+    fn bag_graph_all_totals() {
+        let rules = EXAMPLE_RULES.lines().map(|l| l.to_string()).collect();
+        let graph = BagGraph::from_rules(&parse_rules(rules));
+        let totals = graph.all_totals().unwrap();
+
+        assert_eq!(totals[graph.id("shiny gold").unwrap()], 32);
+        assert_eq!(
+            totals[graph.id("light red").unwrap()],
+            1 + 33 + 2 * (1 + 75)
+        );
+    }
+
+    #[test]
+    fn bag_graph_shared_bags_and_overflow() {
+        // Each red bag holds the next red bag directly and again through a
+        // blue bag, so the totals double every level. Without memoization
+        // this would visit 2^150 bags.
+        let rules: Vec<String> = (0..150)
+            .map(|level| {
+                format!(
+                    "level{} red bags contain 1 level{} red bag, 1 level{} blue bag.",
+                    level,
+                    level + 1,
+                    level + 1
+                )
+            })
+            .chain((0..150).map(|level| {
+                format!(
+                    "level{} blue bags contain 1 level{} red bag.",
+                    level + 1,
+                    level + 1
+                )
+            }))
+            .chain(std::iter::once(
+                "level150 red bags contain no other bags.".to_string(),
+            ))
+            .collect();
+        let graph = BagGraph::from_rules(&parse_rules(rules));
+
+        assert_eq!(
+            graph.total_inside(graph.id("level30 red").unwrap()),
+            Ok(3 * ((1 << 120) - 1))
+        );
+        assert_eq!(
+            graph.total_inside(graph.id("level0 red").unwrap()),
+            Err(BagError::Overflow)
+        );
+    }
+
+    #[test]
+    fn bag_graph_cycle() {
+        let rules = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 1 dark blue bag, 1 faded blue bag.
+dark blue bags contain 3 shiny gold bags.
+faded blue bags contain no other bags."
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let graph = BagGraph::from_rules(&parse_rules(rules));
+        let faded_blue = graph.id("faded blue").unwrap();
+        let shiny_gold = graph.id("shiny gold").unwrap();
+
+        assert_eq!(
+            graph.total_inside(shiny_gold),
+            Err(BagError::Cycle(vec![
+                "shiny gold".to_string(),
+                "dark red".to_string(),
+                "dark blue".to_string()
+            ]))
+        );
+        assert_eq!(graph.total_inside(faded_blue), Ok(0));
+        assert!(graph.ancestors(shiny_gold).contains(&shiny_gold));
+    }
+
+    #[test]
+    fn rules_to_dot_full() {
+        let rules = parse_rules(
+            "bright white bags contain 1 shiny gold bag.
+shiny gold bags contain 2 dark olive bags.
+dark olive bags contain no other bags."
+                .lines()
+                .map(|l| l.to_string())
+                .collect(),
+        );
+        let expected = r#"digraph bags {
+  node [shape=box];
+  "bright white";
+  "dark olive";
+  "shiny gold";
+  "bright white" -> "shiny gold" [label="1"];
+  "shiny gold" -> "dark olive" [label="2"];
+}"#;
+
+        assert_eq!(
+            rules_to_dot(&rules, &DotOptions::default()),
+            Ok(expected.to_string())
+        );
+    }
+
+    #[test]
+    fn rules_to_dot_focus_and_depth() {
+        let rules = parse_rules(EXAMPLE_RULES.lines().map(|l| l.to_string()).collect());
+        let options = DotOptions {
+            focus: Some(DotFocus {
+                bag: "shiny gold".to_string(),
+                direction: Direction::Holders,
+            }),
+            max_depth: Some(1),
+        };
+        let expected = r#"digraph bags {
+  node [shape=box];
+  "bright white" [style=filled, fillcolor=lightblue];
+  "muted yellow" [style=filled, fillcolor=lightblue];
+  "shiny gold" [style=filled, fillcolor=gold];
+  "bright white" -> "shiny gold" [label="1", color=blue, penwidth=2];
+  "muted yellow" -> "shiny gold" [label="2", color=blue, penwidth=2];
+}"#;
+
+        assert_eq!(rules_to_dot(&rules, &options), Ok(expected.to_string()));
+
+        let unlimited = DotOptions {
+            max_depth: None,
+            ..options
+        };
+        let dot = rules_to_dot(&rules, &unlimited).unwrap();
+
+        assert_eq!(dot.matches("fillcolor=lightblue").count(), 4);
+        assert_eq!(dot.matches(" -> ").count(), 13);
+        assert_eq!(dot.matches("penwidth").count(), 6);
+    }
+
+    #[test]
+    fn rules_to_dot_depth_without_focus() {
+        let rules = parse_rules(EXAMPLE_RULES.lines().map(|l| l.to_string()).collect());
+        let options = DotOptions {
+            focus: None,
+            max_depth: Some(1),
+        };
+        let dot = rules_to_dot(&rules, &options).unwrap();
+
+        assert!(dot.contains("\"light red\" -> \"muted yellow\" [label=\"2\"];"));
+        assert!(!dot.contains("shiny gold"));
+        assert_eq!(
+            rules_to_dot(
+                &rules,
+                &DotOptions {
+                    focus: Some(DotFocus {
+                        bag: "plaid purple".to_string(),
+                        direction: Direction::Contents,
+                    }),
+                    max_depth: None,
+                }
+            ),
+            Err(BagError::UnknownBag("plaid purple".to_string()))
+        );
+        assert_eq!(dot_id("say \"hi\""), r#""say \"hi\"""#);
+    }
+
+    #[test]
+    fn query_parse() {
+        assert_eq!(
+            "holding  5   dark red".parse(),
+            Ok(Query::Holding {
+                at_least: 5,
+                bag: "dark red".to_string()
+            })
+        );
+        assert_eq!(
+            "count path light red -> faded blue".parse(),
+            Ok(Query::Count(Box::new(Query::Path {
+                from: "light red".to_string(),
+                to: "faded blue".to_string()
+            })))
+        );
+        assert_eq!("".parse::<Query>(), Err(QueryError::Empty));
+        assert_eq!(
+            "find shiny gold".parse::<Query>(),
+            Err(QueryError::UnknownCommand("find".to_string()))
+        );
+        assert_eq!(
+            "holding x dark red".parse::<Query>(),
+            Err(QueryError::InvalidNumber("x".to_string()))
+        );
+        assert_eq!(
+            "holders".parse::<Query>(),
+            Err(QueryError::MissingBag("holders"))
+        );
+        assert_eq!(
+            "path light red".parse::<Query>(),
+            Err(QueryError::MissingArrow)
+        );
+    }
+
+    #[test]
+    fn query_rules_example() {
+        let rules = parse_rules(EXAMPLE_RULES.lines().map(|l| l.to_string()).collect());
+        let bags = |names: &[&str]| {
+            Ok(QueryResult::Bags(
+                names.iter().map(|name| name.to_string()).collect(),
+            ))
+        };
+
+        assert_eq!(
+            query_rules(&rules, "leaves"),
+            bags(&["dotted black", "faded blue"])
+        );
+        assert_eq!(
+            query_rules(&rules, "roots"),
+            bags(&["dark orange", "light red"])
+        );
+        assert_eq!(
+            query_rules(&rules, "count holders shiny gold"),
+            Ok(QueryResult::Count(4))
+        );
+        assert_eq!(
+            query_rules(&rules, "total shiny gold"),
+            Ok(QueryResult::Count(32))
+        );
+        // muted yellow holds 35 faded blue bags, 9 directly and 13 inside
+        // each of its shiny gold bags
+        assert_eq!(
+            query_rules(&rules, "holding 36 faded blue"),
+            bags(&["dark orange", "light red"])
+        );
+        assert_eq!(
+            query_rules(&rules, "holding 0 shiny gold"),
+            query_rules(&rules, "holders shiny gold")
+        );
+        assert_eq!(
+            query_rules(&rules, "path light red -> dotted black"),
+            bags(&[
+                "light red",
+                "bright white",
+                "shiny gold",
+                "dark olive",
+                "dotted black"
+            ])
+        );
+        assert_eq!(
+            query_rules(&rules, "path faded blue -> light red"),
+            bags(&[])
+        );
+        assert_eq!(
+            query_rules(&rules, "count total shiny gold"),
+            Err(QueryError::NotAList)
+        );
+        assert_eq!(
+            query_rules(&rules, "contents plaid purple"),
+            Err(QueryError::Bag(BagError::UnknownBag(
+                "plaid purple".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn query_repl_session() {
+        let rules = parse_rules(EXAMPLE_RULES.lines().map(|l| l.to_string()).collect());
+        let graph = BagGraph::from_rules(&rules);
+        let input = "leaves\n\ncount roots\npath faded blue -> shiny gold\nfind\nquit\nleaves\n";
+        let mut output = vec![];
+        run_query_repl(&graph, input.as_bytes(), &mut output).unwrap();
+        let expected = "> dotted black
+faded blue
+> > 2
+> no bags
+> error: unknown command `find`
+> ";
+
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_rule_text_variations() {
+        let text = "  light   red bags contain 1 bright white bags , 2 muted yellow bag
+bright white bag contain 1 shiny gold bag.
+
+very  pale shiny gold bags contain no other bag
+faded blue bags contain no other bags.";
+        let rules = parse_rule_text(text).unwrap();
+
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules["light red"]["bright white"], 1);
+        assert_eq!(rules["light red"]["muted yellow"], 2);
+        assert_eq!(rules["bright white"]["shiny gold"], 1);
+        assert!(rules["very pale shiny gold"].is_empty());
+        assert!(rules["faded blue"].is_empty());
+    }
+
+    #[test]
+    fn parse_rule_text_errors() {
+        let error = |text: &str| parse_rule_text(text).unwrap_err();
+
+        assert_eq!(
+            error("faded blue bags contain no other bags.\nshiny gold bags hold 2 red bags."),
+            RuleParseError {
+                line: 2,
+                column: 33,
+                kind: RuleErrorKind::MissingContain
+            }
+        );
+        assert_eq!(
+            error("shiny gold bags contain two red bags."),
+            RuleParseError {
+                line: 1,
+                column: 25,
+                kind: RuleErrorKind::InvalidCount("two".to_string())
+            }
+        );
+        assert_eq!(
+            error("shiny gold boxes contain 2 red bags."),
+            RuleParseError {
+                line: 1,
+                column: 12,
+                kind: RuleErrorKind::ExpectedBagWord("boxes".to_string())
+            }
+        );
+        assert_eq!(
+            error("bags contain 2 red bags."),
+            RuleParseError {
+                line: 1,
+                column: 1,
+                kind: RuleErrorKind::MissingColor
+            }
+        );
+        assert_eq!(
+            error("shiny gold bags contain 2 red bags,, 1 blue bag."),
+            RuleParseError {
+                line: 1,
+                column: 36,
+                kind: RuleErrorKind::MissingContents
+            }
+        );
+        assert_eq!(
+            error("shiny gold bags contain 2 red bags, 1 red bag."),
+            RuleParseError {
+                line: 1,
+                column: 37,
+                kind: RuleErrorKind::DuplicateContent("red".to_string())
+            }
+        );
+        assert_eq!(
+            error("shiny gold bags contain 2 red. bags."),
+            RuleParseError {
+                line: 1,
+                column: 30,
+                kind: RuleErrorKind::UnexpectedToken(".".to_string())
+            }
+        );
+        assert_eq!(
+            error("red bags contain no other bags.\n red bags contain 1 blue bag."),
+            RuleParseError {
+                line: 2,
+                column: 2,
+                kind: RuleErrorKind::DuplicateRule("red".to_string())
+            }
+        );
+        assert_eq!(
+            error("shiny gold boxes contain 2 red bags.").to_string(),
+            "line 1, column 12: expected `bag` or `bags`, found `boxes`"
+        );
+    }
+
+    #[test]
+    fn write_rules_round_trips() {
+        let rules = parse_rule_text(EXAMPLE_RULES).unwrap();
+        let written = write_rules(&rules);
+        let lines: Vec<&str> = written.lines().collect();
+
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "bright white bags contain 1 shiny gold bag.");
+        assert_eq!(
+            lines[1],
+            "dark olive bags contain 4 dotted black bags, 3 faded blue bags."
+        );
+        assert_eq!(lines[3], "dotted black bags contain no other bags.");
+        assert_eq!(write_rules(&parse_rule_text(&written).unwrap()), written);
+        assert_eq!(parse_rule_text(&written), Ok(rules));
+    }
+
     /// Part2
     #[test]
     fn total_bags_from_input() {
         let expected = 2976;
 
         let rules = load_as_vec_string("day7");
-        let actual = total_bags_inside_shiny_gold(rules);
+        let actual = total_bags_inside_shiny_gold(rules).unwrap();
         println!(
             "{}{}",
             "Number of bags inside shiny gold bag: ".green().bold(),
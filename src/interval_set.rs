@@ -0,0 +1,285 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A set of `usize` values stored as sorted, inclusive `(low, high)` ranges.
+/// Ranges never overlap or touch, so equal sets always compare equal.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct IntervalSet {
+    ranges: Vec<(usize, usize)>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ParseIntervalError {
+    /// A range wasn't formatted as `low-high`
+    MissingDash(String),
+    /// One side of a range wasn't a number
+    InvalidNumber(String),
+    /// A range's low value was above its high value
+    Reversed(usize, usize),
+}
+
+impl IntervalSet {
+    pub fn new() -> IntervalSet {
+        IntervalSet { ranges: vec![] }
+    }
+
+    /// Builds a set from inclusive ranges in any order, merging any that
+    /// overlap or touch.
+    pub fn from_ranges(mut ranges: Vec<(usize, usize)>) -> IntervalSet {
+        ranges.retain(|range| range.0 <= range.1);
+        ranges.sort_unstable();
+
+        let mut normalized: Vec<(usize, usize)> = vec![];
+        for range in ranges {
+            match normalized.last_mut() {
+                Some(prev) if prev.1.saturating_add(1) >= range.0 => {
+                    prev.1 = prev.1.max(range.1);
+                }
+                _ => normalized.push(range),
+            }
+        }
+
+        IntervalSet { ranges: normalized }
+    }
+
+    /// The normalized ranges, sorted from lowest to highest
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges
+    }
+
+    /// The number of values in the set, saturating at `usize::MAX`
+    pub fn len(&self) -> usize {
+        self.ranges.iter().fold(0usize, |count, range| {
+            count.saturating_add(range.1 - range.0).saturating_add(1)
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Binary searches for the only range that could hold `value`
+    pub fn contains(&self, value: usize) -> bool {
+        let i = self.ranges.partition_point(|range| range.1 < value);
+
+        i < self.ranges.len() && self.ranges[i].0 <= value
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        IntervalSet::from_ranges(
+            self.ranges
+                .iter()
+                .chain(other.ranges.iter())
+                .copied()
+                .collect(),
+        )
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut ranges = vec![];
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (self.ranges[i], other.ranges[j]);
+            let low = a.0.max(b.0);
+            let high = a.1.min(b.1);
+            if low <= high {
+                ranges.push((low, high));
+            }
+
+            if a.1 < b.1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet { ranges }
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        self.intersection(&other.complement(0, usize::MAX))
+    }
+
+    /// Every value from `low` to `high`, inclusive, that isn't in the set
+    pub fn complement(&self, low: usize, high: usize) -> IntervalSet {
+        let mut ranges = vec![];
+        let mut next = low;
+
+        for &(range_low, range_high) in &self.ranges {
+            if range_high < low {
+                continue;
+            }
+            if range_low > high {
+                break;
+            }
+            if range_low > next {
+                ranges.push((next, range_low - 1));
+            }
+            match range_high.checked_add(1) {
+                Some(after) => next = next.max(after),
+                None => return IntervalSet { ranges },
+            }
+        }
+
+        if next <= high {
+            ranges.push((next, high));
+        }
+
+        IntervalSet { ranges }
+    }
+}
+
+/// Parses ranges formatted like `1-3 or 5-7`
+impl FromStr for IntervalSet {
+    type Err = ParseIntervalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ranges = s
+            .split(" or ")
+            .map(|range| {
+                let dash_i = range
+                    .find('-')
+                    .ok_or_else(|| ParseIntervalError::MissingDash(range.to_string()))?;
+                let (raw_low, raw_high) = (&range[..dash_i], &range[dash_i + 1..]);
+                let parse_side = |side: &str| {
+                    side.trim()
+                        .parse::<usize>()
+                        .map_err(|_| ParseIntervalError::InvalidNumber(side.trim().to_string()))
+                };
+                let (low, high) = (parse_side(raw_low)?, parse_side(raw_high)?);
+
+                if low > high {
+                    Err(ParseIntervalError::Reversed(low, high))
+                } else {
+                    Ok((low, high))
+                }
+            })
+            .collect::<Result<Vec<(usize, usize)>, ParseIntervalError>>()?;
+
+        Ok(IntervalSet::from_ranges(ranges))
+    }
+}
+
+/// Writes ranges in the same `1-3 or 5-7` format that `from_str` reads
+impl fmt::Display for IntervalSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ranges: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|range| format!("{}-{}", range.0, range.1))
+            .collect();
+
+        write!(f, "{}", ranges.join(" or "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_ranges_normalizes() {
+        let expected = [(1, 11), (13, 40), (45, 50)];
+        let actual = IntervalSet::from_ranges(vec![(13, 40), (6, 11), (1, 3), (45, 50), (4, 7)]);
+
+        assert_eq!(actual.ranges(), &expected[..]);
+    }
+
+    #[test]
+    fn contains_test() {
+        let set = IntervalSet::from_ranges(vec![(1, 3), (5, 7), (10, 10)]);
+        let contained: Vec<usize> = (0..12).filter(|&n| set.contains(n)).collect();
+
+        assert_eq!(contained, vec![1, 2, 3, 5, 6, 7, 10]);
+        assert!(!IntervalSet::new().contains(0));
+    }
+
+    #[test]
+    fn len_test() {
+        assert_eq!(IntervalSet::new().len(), 0);
+        assert_eq!(IntervalSet::from_ranges(vec![(1, 3), (5, 5)]).len(), 4);
+        assert_eq!(
+            IntervalSet::from_ranges(vec![(0, usize::MAX)]).len(),
+            usize::MAX
+        );
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = IntervalSet::from_ranges(vec![(1, 5), (10, 20)]);
+        let b = IntervalSet::from_ranges(vec![(4, 12), (18, 30)]);
+
+        assert_eq!(a.union(&b).ranges(), &[(1, 30)]);
+        assert_eq!(a.intersection(&b).ranges(), &[(4, 5), (10, 12), (18, 20)]);
+        assert_eq!(a.difference(&b).ranges(), &[(1, 3), (13, 17)]);
+        assert_eq!(b.difference(&a).ranges(), &[(6, 9), (21, 30)]);
+    }
+
+    #[test]
+    fn set_operations_match_brute_force() {
+        let sets = vec![
+            IntervalSet::new(),
+            IntervalSet::from_ranges(vec![(0, 0), (3, 5), (9, 12)]),
+            IntervalSet::from_ranges(vec![(2, 4), (6, 6), (8, 15)]),
+            IntervalSet::from_ranges(vec![(0, 15)]),
+        ];
+
+        for a in &sets {
+            for b in &sets {
+                for n in 0..=16 {
+                    assert_eq!(a.union(b).contains(n), a.contains(n) || b.contains(n));
+                    assert_eq!(
+                        a.intersection(b).contains(n),
+                        a.contains(n) && b.contains(n)
+                    );
+                    assert_eq!(a.difference(b).contains(n), a.contains(n) && !b.contains(n));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn complement_test() {
+        let set = IntervalSet::from_ranges(vec![(1, 3), (5, 7), (12, 20)]);
+
+        assert_eq!(set.complement(0, 15).ranges(), &[(0, 0), (4, 4), (8, 11)]);
+        assert_eq!(set.complement(4, 6).ranges(), &[(4, 4)]);
+        assert_eq!(set.complement(13, 18).ranges(), &[]);
+        assert_eq!(IntervalSet::new().complement(2, 4).ranges(), &[(2, 4)]);
+        assert_eq!(
+            IntervalSet::from_ranges(vec![(5, usize::MAX)])
+                .complement(0, usize::MAX)
+                .ranges(),
+            &[(0, 4)]
+        );
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let set: IntervalSet = "1-3 or 5-7".parse().unwrap();
+
+        assert_eq!(set.ranges(), &[(1, 3), (5, 7)]);
+        assert_eq!(set.to_string(), "1-3 or 5-7");
+        assert_eq!(
+            " 6 - 11 or 33-44 ".parse::<IntervalSet>().unwrap().ranges(),
+            &[(6, 11), (33, 44)]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "1-3 or 57".parse::<IntervalSet>(),
+            Err(ParseIntervalError::MissingDash("57".to_string()))
+        );
+        assert_eq!(
+            "1-x".parse::<IntervalSet>(),
+            Err(ParseIntervalError::InvalidNumber("x".to_string()))
+        );
+        assert_eq!(
+            "7-5".parse::<IntervalSet>(),
+            Err(ParseIntervalError::Reversed(7, 5))
+        );
+    }
+}
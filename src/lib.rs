@@ -10,7 +10,9 @@ mod encoding_error;
 mod handheld_halting;
 mod handy_haversacks;
 mod input_utils;
+mod interval_set;
 mod monster_messages;
+mod ntheory;
 mod passport_processing;
 mod password_philosophy;
 mod rain_risk;
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+
+#[derive(PartialEq, Debug)]
+pub enum CrtError {
+    /// The congruence at this index contradicts the ones before it
+    NoSolution(usize),
+    /// The combined modulus is larger than `u128::MAX`
+    Overflow,
+    /// The congruence at this index has a modulus of 0
+    ZeroModulus(usize),
+}
+
+/// Extended Euclidean algorithm. Returns `(gcd, x, y)` where `a*x + b*y = gcd`.
+/// Neither input can be `i128::MIN`, whose magnitude doesn't fit in an
+/// `i128`. Use `mod_inverse` to work with `u128` values.
+pub fn egcd(a: i128, b: i128) -> (i128, i128, i128) {
+    assert!(
+        a != i128::MIN && b != i128::MIN,
+        "inputs must be above i128::MIN"
+    );
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_x, mut x) = (1, 0);
+    let (mut old_y, mut y) = (0, 1);
+
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_x, x) = (x, old_x - q * x);
+        (old_y, y) = (y, old_y - q * y);
+    }
+
+    if old_r < 0 {
+        (-old_r, -old_x, -old_y)
+    } else {
+        (old_r, old_x, old_y)
+    }
+}
+
+pub fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+
+    a
+}
+
+/// `(a + b) % m` for `a` and `b` already below `m`, without overflowing
+pub fn add_mod(a: u128, b: u128, m: u128) -> u128 {
+    if a >= m - b {
+        a - (m - b)
+    } else {
+        a + b
+    }
+}
+
+/// `(a - b) % m` for `a` and `b` already below `m`
+pub fn sub_mod(a: u128, b: u128, m: u128) -> u128 {
+    if a >= b {
+        a - b
+    } else {
+        m - (b - a)
+    }
+}
+
+/// `(a * b) % m`, falling back to doubling and adding when the product
+/// doesn't fit in a `u128`
+pub fn mul_mod(a: u128, b: u128, m: u128) -> u128 {
+    let (mut a, mut b) = (a % m, b % m);
+    if let Some(product) = a.checked_mul(b) {
+        return product % m;
+    }
+
+    let mut result = 0;
+    while b > 0 {
+        if b & 1 == 1 {
+            result = add_mod(result, a, m);
+        }
+        a = add_mod(a, a, m);
+        b >>= 1;
+    }
+
+    result
+}
+
+/// `base.pow(exp) % m` by repeated squaring
+pub fn mod_pow(base: u128, mut exp: u128, m: u128) -> u128 {
+    assert!(m > 0, "modulus must be positive");
+    let mut base = base % m;
+    let mut result = 1 % m;
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+
+    result
+}
+
+/// The `x` below `m` where `a * x = 1 (mod m)`, if `a` and `m` are coprime
+pub fn mod_inverse(a: u128, m: u128) -> Option<u128> {
+    if m == 0 {
+        return None;
+    }
+
+    // Euclid's algorithm, tracking only a's coefficient, kept reduced mod m
+    let (mut old_r, mut r) = (a % m, m);
+    let (mut old_t, mut t) = (1 % m, 0);
+
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_t, t) = (t, sub_mod(old_t, mul_mod(q, t, m), m));
+    }
+
+    if old_r == 1 {
+        Some(old_t)
+    } else {
+        None
+    }
+}
+
+/// Solves `x = remainder (mod modulus)` for every `(remainder, modulus)` pair.
+/// Moduli don't need to be coprime, but must be positive. Returns the
+/// smallest non-negative `x` along with the combined modulus that solutions
+/// repeat with.
+pub fn crt(congruences: &[(u128, u128)]) -> Result<(u128, u128), CrtError> {
+    let mut x: u128 = 0;
+    let mut period: u128 = 1;
+
+    for (i, &(remainder, modulus)) in congruences.iter().enumerate() {
+        if modulus == 0 {
+            return Err(CrtError::ZeroModulus(i));
+        }
+        let remainder = remainder % modulus;
+        let g = gcd(period, modulus);
+        let diff = sub_mod(remainder, x % modulus, modulus);
+        if !diff.is_multiple_of(g) {
+            return Err(CrtError::NoSolution(i));
+        }
+
+        // x + period * k = remainder (mod modulus), so
+        // k = diff / g * (period / g)⁻¹ (mod modulus / g)
+        let reduced = modulus / g;
+        let inv = mod_inverse(period / g, reduced).unwrap();
+        let k = mul_mod(diff / g, inv, reduced);
+
+        let new_period = period.checked_mul(reduced).ok_or(CrtError::Overflow)?;
+        x += k * period;
+        period = new_period;
+    }
+
+    Ok((x, period))
+}
+
+/// Baby-step giant-step search for the smallest `x` where
+/// `base.pow(x) = target (mod m)`. Factors shared by `base` and `m` are
+/// divided out first, so `m` doesn't need to be prime. Stores about `√m`
+/// values, so `m` has to be small enough for that to fit in memory. Returns
+/// `None` when `m` is 0.
+pub fn discrete_log(base: u128, target: u128, m: u128) -> Option<u128> {
+    if m == 0 {
+        return None;
+    }
+    if m == 1 {
+        return Some(0);
+    }
+    let (base, mut target, mut m) = (base % m, target % m, m);
+
+    // Each shared factor divided out below is at least 2, so checking answers
+    // below the bit length of m directly covers all of those steps
+    let mut power = 1 % m;
+    for x in 0..(128 - m.leading_zeros()) as u128 {
+        if power == target {
+            return Some(x);
+        }
+        power = mul_mod(power, base, m);
+    }
+
+    let mut shifts = 0;
+    let mut coefficient = 1 % m;
+    loop {
+        let g = gcd(base, m);
+        if g == 1 {
+            break;
+        }
+        if !target.is_multiple_of(g) {
+            return None;
+        }
+        m /= g;
+        target /= g;
+        coefficient = mul_mod(coefficient, base / g, m);
+        shifts += 1;
+    }
+
+    // coefficient * base^y = target (mod m), with base and m now coprime
+    if coefficient == target {
+        return Some(shifts);
+    }
+    let n = ceil_sqrt(m);
+    let mut baby_steps: HashMap<u128, u128> = HashMap::new();
+    let mut step = target;
+    for j in 0..n {
+        baby_steps.insert(step, j);
+        step = mul_mod(step, base, m);
+    }
+
+    let giant = mod_pow(base, n, m);
+    let mut value = coefficient;
+    for i in 1..=n {
+        value = mul_mod(value, giant, m);
+        if let Some(j) = baby_steps.get(&value) {
+            return Some(shifts + i * n - j);
+        }
+    }
+
+    None
+}
+
+fn ceil_sqrt(n: u128) -> u128 {
+    let mut root = (n as f64).sqrt() as u128;
+    while root.checked_mul(root).is_none_or(|sq| sq > n) {
+        root -= 1;
+    }
+    while (root + 1).checked_mul(root + 1).is_some_and(|sq| sq <= n) {
+        root += 1;
+    }
+
+    if root * root == n {
+        root
+    } else {
+        root + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MERSENNE_127: u128 = (1 << 127) - 1;
+
+    #[test]
+    fn egcd_exhaustive() {
+        for a in -30..=30 {
+            for b in -30..=30 {
+                let (g, x, y) = egcd(a, b);
+
+                assert_eq!(a * x + b * y, g);
+                assert_eq!(g as u128, gcd(a.unsigned_abs(), b.unsigned_abs()));
+            }
+        }
+        assert_eq!(egcd(i128::MAX, -i128::MAX), (i128::MAX, 0, -1));
+    }
+
+    #[test]
+    fn mul_mod_large() {
+        let a = u128::MAX - 1;
+        let m = u128::MAX;
+
+        assert_eq!(mul_mod(a, a, m), 1);
+        assert_eq!(mul_mod(MERSENNE_127 - 1, 2, MERSENNE_127), MERSENNE_127 - 2);
+        assert_eq!(mul_mod(1 << 100, 1 << 100, MERSENNE_127), 1 << 73);
+    }
+
+    #[test]
+    fn mod_pow_exhaustive() {
+        for m in 1..=40u128 {
+            for base in 0..m + 3 {
+                let mut expected = 1 % m;
+                for exp in 0..50 {
+                    assert_eq!(mod_pow(base, exp, m), expected);
+                    expected = expected * base % m;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mod_pow_large() {
+        // Fermat's little theorem for the prime 2^127 - 1
+        assert_eq!(mod_pow(3, MERSENNE_127 - 1, MERSENNE_127), 1);
+        assert_eq!(mod_pow(u128::MAX, 2, u128::MAX - 1), 1);
+    }
+
+    #[test]
+    fn mod_inverse_exhaustive() {
+        for m in 1..=60u128 {
+            for a in 0..m * 2 {
+                let expected = (0..m).find(|x| a * x % m == 1 % m);
+                let expected = expected.filter(|_| gcd(a, m) == 1);
+
+                assert_eq!(mod_inverse(a, m), expected, "a = {}, m = {}", a, m);
+            }
+        }
+        assert_eq!(mod_inverse(5, 0), None);
+    }
+
+    #[test]
+    fn mod_inverse_large() {
+        let inv = mod_inverse(123_456_789, MERSENNE_127).unwrap();
+
+        assert_eq!(mul_mod(inv, 123_456_789, MERSENNE_127), 1);
+    }
+
+    #[test]
+    fn crt_exhaustive() {
+        for m1 in 1..=12u128 {
+            for m2 in 1..=12u128 {
+                for r1 in 0..m1 {
+                    for r2 in 0..m2 {
+                        let lcm = m1 * m2 / gcd(m1, m2);
+                        let expected = (0..lcm).find(|x| x % m1 == r1 && x % m2 == r2);
+                        let actual = crt(&[(r1, m1), (r2, m2)]);
+
+                        match expected {
+                            Some(x) => assert_eq!(actual, Ok((x, lcm))),
+                            None => assert_eq!(actual, Err(CrtError::NoSolution(1))),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crt_many_and_overflow() {
+        assert_eq!(crt(&[]), Ok((0, 1)));
+        assert_eq!(crt(&[(2, 3), (3, 5), (2, 7)]), Ok((23, 105)));
+        assert_eq!(crt(&[(1, 4), (2, 6), (0, 5)]), Err(CrtError::NoSolution(1)));
+        assert_eq!(
+            crt(&[(1, MERSENNE_127), (0, 2), (0, 3)]),
+            Err(CrtError::Overflow)
+        );
+        assert_eq!(crt(&[(5, MERSENNE_127), (1, 2)]), Ok((5, MERSENNE_127 * 2)));
+        assert_eq!(crt(&[(1, 4), (0, 0)]), Err(CrtError::ZeroModulus(1)));
+    }
+
+    #[test]
+    fn discrete_log_exhaustive() {
+        for m in 1..=50u128 {
+            for base in 0..m {
+                for target in 0..m {
+                    let mut power = 1 % m;
+                    let mut expected = None;
+                    for x in 0..=m {
+                        if power == target {
+                            expected = Some(x);
+                            break;
+                        }
+                        power = power * base % m;
+                    }
+
+                    assert_eq!(
+                        discrete_log(base, target, m),
+                        expected,
+                        "{}^x = {} (mod {})",
+                        base,
+                        target,
+                        m
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn discrete_log_large() {
+        let m = 1_000_000_007;
+        let x = discrete_log(5, 123_456_789, m).unwrap();
+
+        assert_eq!(mod_pow(5, x, m), 123_456_789);
+        assert!(x < m);
+        assert_eq!(discrete_log(5, 3, 0), None);
+    }
+
+    #[test]
+    fn ceil_sqrt_test() {
+        let expected: Vec<u128> = vec![0, 1, 2, 2, 2, 3, 3, 3, 3, 3, 4];
+        let actual: Vec<u128> = (0..=10).map(ceil_sqrt).collect();
+
+        assert_eq!(actual, expected);
+        assert_eq!(ceil_sqrt(u128::MAX), 1 << 64);
+    }
+}
@@ -1,23 +1,57 @@
-use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 struct FerryTracker {
     x: isize,
     y: isize,
-    dir: char,
+    /// Unit vector (x, y) for the direction the ship faces
+    dir: (isize, isize),
     way_x: isize,
     way_y: isize,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum NavInstruction {
+    North(isize),
+    South(isize),
+    East(isize),
+    West(isize),
+    /// Degrees, always a multiple of 90
+    Left(isize),
+    /// Degrees, always a multiple of 90
+    Right(isize),
+    Forward(isize),
+}
+
+#[derive(PartialEq, Debug)]
+enum ParseNavError {
+    Empty,
+    UnknownAction(char),
+    InvalidValue(String),
+    /// Turns must be a multiple of 90 degrees
+    InvalidAngle(isize),
+}
+
+const EAST: (isize, isize) = (0, 1);
+
+/// Clockwise rotation matrices over (x, y), i.e. (north, east), indexed by
+/// the number of quarter turns.
+const QUARTER_TURNS: [[[isize; 2]; 2]; 4] = [
+    [[1, 0], [0, 1]],
+    [[0, -1], [1, 0]],
+    [[-1, 0], [0, -1]],
+    [[0, 1], [-1, 0]],
+];
+
 fn distance_traveled(nav_instructions: Vec<String>) -> usize {
-    let parsed_nav: Vec<(char, isize)> = nav_instructions
-        .into_iter()
-        .map(|n_i| parse_nav_instr(n_i))
-        .collect();
+    let parsed_nav: Vec<NavInstruction> =
+        nav_instructions.into_iter().map(parse_nav_instr).collect();
 
     let start_pos = FerryTracker {
         x: 0,
         y: 0,
-        dir: 'E',
+        dir: EAST,
         way_x: 0,
         way_y: 0,
     };
@@ -27,67 +61,75 @@ fn distance_traveled(nav_instructions: Vec<String>) -> usize {
     (end_pos.x.abs() + end_pos.y.abs()) as usize
 }
 
-fn navigate(nav_instructions: Vec<(char, isize)>, position: FerryTracker) -> FerryTracker {
-    nav_instructions
-        .into_iter()
-        .fold(position, |pos, n_i| match n_i.0 {
-            'N' | 'S' | 'E' | 'W' => travel_direction(n_i, pos),
-            'L' | 'R' => change_heading(n_i, pos),
-            'F' => move_forward(n_i, pos),
-            _ => panic!("Invalid instruction char"),
-        })
+fn navigate(nav_instructions: Vec<NavInstruction>, position: FerryTracker) -> FerryTracker {
+    nav_instructions.into_iter().fold(position, navigate_step)
 }
 
-fn travel_direction(instruction: (char, isize), position: FerryTracker) -> FerryTracker {
-    let pos = match instruction.0 {
-        'N' => (position.x + instruction.1, position.y),
-        'S' => (position.x - instruction.1, position.y),
-        'E' => (position.x, position.y + instruction.1),
-        'W' => (position.x, position.y - instruction.1),
-        _ => panic!("Invalid direction char"),
-    };
+fn navigate_step(pos: FerryTracker, n_i: NavInstruction) -> FerryTracker {
+    match n_i {
+        NavInstruction::Left(degrees) => change_heading(-degrees, pos),
+        NavInstruction::Right(degrees) => change_heading(degrees, pos),
+        NavInstruction::Forward(value) => move_forward(value, pos),
+        compass => {
+            let (x, y) = compass_vector(compass);
+            FerryTracker {
+                x: pos.x + x,
+                y: pos.y + y,
+                ..pos
+            }
+        }
+    }
+}
+
+/// The (x, y) offset of a `North`, `South`, `East` or `West` instruction,
+/// or (0, 0) for any other instruction
+fn compass_vector(instruction: NavInstruction) -> (isize, isize) {
+    match instruction {
+        NavInstruction::North(value) => (value, 0),
+        NavInstruction::South(value) => (-value, 0),
+        NavInstruction::East(value) => (0, value),
+        NavInstruction::West(value) => (0, -value),
+        _ => (0, 0),
+    }
+}
 
+/// Rotates the heading clockwise by `degrees`, which may be negative
+fn change_heading(degrees: isize, position: FerryTracker) -> FerryTracker {
     FerryTracker {
-        x: pos.0,
-        y: pos.1,
+        dir: rotate(position.dir, degrees),
         ..position
     }
 }
 
-fn change_heading(instruction: (char, isize), position: FerryTracker) -> FerryTracker {
-    let dir_map: HashMap<char, isize> = [('N', 0), ('E', 90), ('S', 180), ('W', 270)]
-        .iter()
-        .cloned()
-        .collect();
-
-    let dir_n = dir_map.get(&position.dir).unwrap();
-
-    let pos_neg = if instruction.0 == 'L' { -1 } else { 1 };
-    let calc_dir = dir_n + (instruction.1 * pos_neg);
-    let new_dir_n = ((calc_dir % 360) + 360) % 360;
-    let new_dir = dir_map.into_iter().find(|d| d.1 == new_dir_n).unwrap().0;
-
+fn move_forward(value: isize, position: FerryTracker) -> FerryTracker {
     FerryTracker {
-        dir: new_dir,
+        x: position.x + position.dir.0 * value,
+        y: position.y + position.dir.1 * value,
         ..position
     }
 }
 
-fn move_forward(instruction: (char, isize), position: FerryTracker) -> FerryTracker {
-    travel_direction((position.dir, instruction.1), position)
+/// Rotates an (x, y) vector clockwise by a multiple of 90 degrees with a
+/// single matrix multiplication
+fn rotate(vector: (isize, isize), degrees: isize) -> (isize, isize) {
+    debug_assert!(degrees % 90 == 0, "{} isn't a multiple of 90", degrees);
+    let m = QUARTER_TURNS[(degrees / 90).rem_euclid(4) as usize];
+
+    (
+        m[0][0] * vector.0 + m[0][1] * vector.1,
+        m[1][0] * vector.0 + m[1][1] * vector.1,
+    )
 }
 
 /// Part2
 fn distance_traveled_waypoint(nav_instructions: Vec<String>) -> usize {
-    let parsed_nav: Vec<(char, isize)> = nav_instructions
-        .into_iter()
-        .map(|n_i| parse_nav_instr(n_i))
-        .collect();
+    let parsed_nav: Vec<NavInstruction> =
+        nav_instructions.into_iter().map(parse_nav_instr).collect();
 
     let start_pos = FerryTracker {
         x: 0,
         y: 0,
-        dir: 'E',
+        dir: EAST,
         way_x: 1,
         way_y: 10,
     };
@@ -97,76 +139,648 @@ fn distance_traveled_waypoint(nav_instructions: Vec<String>) -> usize {
     (end_pos.x.abs() + end_pos.y.abs()) as usize
 }
 
-fn navigate_waypoint(nav_instructions: Vec<(char, isize)>, position: FerryTracker) -> FerryTracker {
+fn navigate_waypoint(
+    nav_instructions: Vec<NavInstruction>,
+    position: FerryTracker,
+) -> FerryTracker {
     nav_instructions
         .into_iter()
-        .fold(position, |pos, n_i| match n_i.0 {
-            'N' | 'S' | 'E' | 'W' => move_waypoint(n_i, pos),
-            'L' | 'R' => rotate_waypoint(n_i, pos),
-            'F' => move_to_waypoint(n_i, pos),
-            _ => panic!("Invalid instruction char"),
-        })
+        .fold(position, navigate_waypoint_step)
 }
 
-fn move_waypoint(instruction: (char, isize), position: FerryTracker) -> FerryTracker {
-    let pos = match instruction.0 {
-        'N' => (position.way_x + instruction.1, position.way_y),
-        'S' => (position.way_x - instruction.1, position.way_y),
-        'E' => (position.way_x, position.way_y + instruction.1),
-        'W' => (position.way_x, position.way_y - instruction.1),
-        _ => panic!("Invalid direction char"),
-    };
+fn navigate_waypoint_step(pos: FerryTracker, n_i: NavInstruction) -> FerryTracker {
+    match n_i {
+        NavInstruction::Left(degrees) => rotate_waypoint(-degrees, pos),
+        NavInstruction::Right(degrees) => rotate_waypoint(degrees, pos),
+        NavInstruction::Forward(value) => move_to_waypoint(value, pos),
+        compass => {
+            let (x, y) = compass_vector(compass);
+            FerryTracker {
+                way_x: pos.way_x + x,
+                way_y: pos.way_y + y,
+                ..pos
+            }
+        }
+    }
+}
+
+/// Rotates the waypoint clockwise around the ship by `degrees`, which may be negative
+fn rotate_waypoint(degrees: isize, position: FerryTracker) -> FerryTracker {
+    let (way_x, way_y) = rotate((position.way_x, position.way_y), degrees);
 
     FerryTracker {
-        way_x: pos.0,
-        way_y: pos.1,
+        way_x,
+        way_y,
         ..position
     }
 }
 
-fn rotate_waypoint(instruction: (char, isize), position: FerryTracker) -> FerryTracker {
-    let rotater = match instruction.0 {
-        'L' => |x, y| (y, 0 - x),
-        'R' => |x, y| (0 - y, x),
-        _ => panic!("Unknown rotation char"),
-    };
-
-    let new_pos = rotater(position.way_x, position.way_y);
-    let new_tracker = FerryTracker {
-        way_x: new_pos.0,
-        way_y: new_pos.1,
+fn move_to_waypoint(value: isize, position: FerryTracker) -> FerryTracker {
+    FerryTracker {
+        x: position.x + position.way_x * value,
+        y: position.y + position.way_y * value,
         ..position
-    };
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum SynthesisError {
+    /// A heading must be one of the four compass unit vectors
+    InvalidHeading((isize, isize)),
+    /// The instructions didn't reach the target when run
+    Mismatch(FerryTracker),
+}
+
+/// Builds instructions that sail the ship from `start` to (`east`, `north`),
+/// finishing with the ship facing `heading`, under the part 1 rules.
+fn synthesize_direct(
+    start: FerryTracker,
+    east: isize,
+    north: isize,
+    heading: (isize, isize),
+) -> Result<Vec<NavInstruction>, SynthesisError> {
+    let mut instructions = vec![];
+    instructions.extend(turn_between(start.dir, heading)?);
 
-    let remaining_rotate = instruction.1 - 90;
+    // Forward covers whichever axis the final heading points along
+    let (mut dx, mut dy) = (north - start.x, east - start.y);
+    let along = heading.0 * dx + heading.1 * dy;
+    if along > 0 {
+        instructions.push(NavInstruction::Forward(along));
+        dx -= heading.0 * along;
+        dy -= heading.1 * along;
+    }
+    instructions.extend(compass_moves(dx, dy));
 
-    if remaining_rotate > 0 {
-        rotate_waypoint((instruction.0, remaining_rotate), new_tracker)
+    let end = navigate(instructions.clone(), start);
+    if (end.x, end.y, end.dir) == (north, east, heading) {
+        Ok(instructions)
     } else {
-        new_tracker
+        Err(SynthesisError::Mismatch(end))
     }
 }
 
-fn move_to_waypoint(instruction: (char, isize), position: FerryTracker) -> FerryTracker {
-    let new_pos = FerryTracker {
-        x: position.x + position.way_x,
-        y: position.y + position.way_y,
-        ..position
+/// Builds instructions that sail the ship from `start` to (`east`, `north`),
+/// leaving the waypoint at `waypoint` relative to the ship, under the part 2
+/// rules.
+fn synthesize_waypoint(
+    start: FerryTracker,
+    east: isize,
+    north: isize,
+    waypoint: (isize, isize),
+) -> Result<Vec<NavInstruction>, SynthesisError> {
+    let mut instructions = vec![];
+    let mut current_way = (start.way_x, start.way_y);
+    let delta = (north - start.x, east - start.y);
+
+    if delta != (0, 0) {
+        // Prefer a rotation of the current waypoint that already points at the target
+        let scaled = (0..4).find_map(|q| {
+            let turned = rotate(current_way, q * 90);
+            scale_of(delta, turned).map(|times| (q, turned, times))
+        });
+
+        match scaled {
+            Some((q, turned, times)) => {
+                instructions.extend(quarter_turns(q));
+                current_way = turned;
+                instructions.push(NavInstruction::Forward(times));
+            }
+            None => {
+                instructions.extend(compass_moves(
+                    delta.0 - current_way.0,
+                    delta.1 - current_way.1,
+                ));
+                current_way = delta;
+                instructions.push(NavInstruction::Forward(1));
+            }
+        }
+    }
+
+    match (0..4).find(|&q| rotate(current_way, q * 90) == waypoint) {
+        Some(q) => instructions.extend(quarter_turns(q)),
+        None => instructions.extend(compass_moves(
+            waypoint.0 - current_way.0,
+            waypoint.1 - current_way.1,
+        )),
+    }
+
+    let end = navigate_waypoint(instructions.clone(), start);
+    if (end.x, end.y, end.way_x, end.way_y) == (north, east, waypoint.0, waypoint.1) {
+        Ok(instructions)
+    } else {
+        Err(SynthesisError::Mismatch(end))
+    }
+}
+
+/// The turn from one heading to another, if they differ
+fn turn_between(
+    from: (isize, isize),
+    to: (isize, isize),
+) -> Result<Vec<NavInstruction>, SynthesisError> {
+    (0..4)
+        .find(|&q| rotate(from, q * 90) == to)
+        .map(quarter_turns)
+        .ok_or(SynthesisError::InvalidHeading(to))
+}
+
+/// The shortest way to write `q` clockwise quarter turns
+fn quarter_turns(q: isize) -> Vec<NavInstruction> {
+    match q.rem_euclid(4) {
+        0 => vec![],
+        3 => vec![NavInstruction::Left(90)],
+        q => vec![NavInstruction::Right(q * 90)],
+    }
+}
+
+/// `N`/`S` and `E`/`W` moves covering an (x, y) offset
+fn compass_moves(x: isize, y: isize) -> Vec<NavInstruction> {
+    let mut moves = vec![];
+    match x {
+        0 => (),
+        x if x > 0 => moves.push(NavInstruction::North(x)),
+        x => moves.push(NavInstruction::South(-x)),
+    }
+    match y {
+        0 => (),
+        y if y > 0 => moves.push(NavInstruction::East(y)),
+        y => moves.push(NavInstruction::West(-y)),
+    }
+
+    moves
+}
+
+/// The positive whole number `times` where `target == vector * times`, if any
+fn scale_of(target: (isize, isize), vector: (isize, isize)) -> Option<isize> {
+    let times = if vector.0 != 0 {
+        target.0 / vector.0
+    } else if vector.1 != 0 {
+        target.1 / vector.1
+    } else {
+        return None;
     };
 
-    let remaining_moves = instruction.1 - 1;
+    if times > 0 && (vector.0 * times, vector.1 * times) == target {
+        Some(times)
+    } else {
+        None
+    }
+}
+
+/// Writes instructions back out in the puzzle's input format
+fn format_nav_instrs(nav_instructions: &[NavInstruction]) -> Vec<String> {
+    nav_instructions.iter().map(|n_i| n_i.to_string()).collect()
+}
+
+/// Every position of the ferry and its waypoint, starting with the initial
+/// position and then one after each instruction. Positions are (x, y) as in
+/// `FerryTracker`, so x is north and y is east.
+#[derive(PartialEq, Debug)]
+struct Route {
+    positions: Vec<FerryTracker>,
+}
+
+/// Runs each instruction through `step`, as `navigate` or `navigate_waypoint`
+/// would, keeping every intermediate position.
+fn record_route<F>(nav_instructions: Vec<NavInstruction>, position: FerryTracker, step: F) -> Route
+where
+    F: Fn(FerryTracker, NavInstruction) -> FerryTracker,
+{
+    let mut positions = vec![position];
+    for n_i in nav_instructions {
+        let next = step(*positions.last().unwrap(), n_i);
+        positions.push(next);
+    }
+
+    Route { positions }
+}
+
+impl Route {
+    /// The ship's ((min x, min y), (max x, max y))
+    fn bounding_box(&self) -> ((isize, isize), (isize, isize)) {
+        self.positions.iter().fold(
+            ((isize::MAX, isize::MAX), (isize::MIN, isize::MIN)),
+            |(min, max), pos| {
+                (
+                    (min.0.min(pos.x), min.1.min(pos.y)),
+                    (max.0.max(pos.x), max.1.max(pos.y)),
+                )
+            },
+        )
+    }
+
+    /// The ship position with the largest Manhattan distance from the start
+    fn furthest_point(&self) -> (isize, isize) {
+        let start = self.positions[0];
+
+        self.positions
+            .iter()
+            .max_by_key(|pos| (pos.x - start.x).abs() + (pos.y - start.y).abs())
+            .map(|pos| (pos.x, pos.y))
+            .unwrap()
+    }
+
+    /// Total straight-line distance the ship sailed
+    fn path_length(&self) -> f64 {
+        self.positions
+            .windows(2)
+            .map(|pair| ((pair[1].x - pair[0].x) as f64).hypot((pair[1].y - pair[0].y) as f64))
+            .sum()
+    }
+
+    /// Draws the ship's path as an SVG polyline with north pointing up
+    fn to_svg(&self) -> String {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounding_box();
+        let margin = ((max_x - min_x).max(max_y - min_y) / 20).max(1);
+        let points: Vec<String> = self
+            .positions
+            .iter()
+            .map(|pos| format!("{},{}", pos.y, -pos.x))
+            .collect();
+        let start = self.positions[0];
+        let end = self.positions[self.positions.len() - 1];
+        let stroke = margin as f64 / 4.0;
+
+        [
+            format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
+                min_y - margin,
+                -max_x - margin,
+                max_y - min_y + margin * 2,
+                max_x - min_x + margin * 2
+            ),
+            format!(
+                "  <polyline points=\"{}\" fill=\"none\" stroke=\"navy\" stroke-width=\"{}\"/>",
+                points.join(" "),
+                stroke
+            ),
+            format!(
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"green\"/>",
+                start.y,
+                -start.x,
+                stroke * 2.0
+            ),
+            format!(
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"red\"/>",
+                end.y,
+                -end.x,
+                stroke * 2.0
+            ),
+            "</svg>".to_string(),
+        ]
+        .join("\n")
+    }
+
+    /// Draws the ship's path scaled to fit `width` by `height` characters, with
+    /// north at the top. `S` marks the start, `E` the end and `#` the path.
+    fn to_ascii(&self, width: usize, height: usize) -> String {
+        if width == 0 || height == 0 {
+            return String::new();
+        }
+        let ((min_x, min_y), (max_x, max_y)) = self.bounding_box();
+        let scale = |value: isize, min: isize, max: isize, cells: usize| -> usize {
+            if max == min {
+                (cells - 1) / 2
+            } else {
+                (((value - min) as f64 / (max - min) as f64) * (cells - 1) as f64).round() as usize
+            }
+        };
+        let to_cell = |x: isize, y: isize| {
+            (
+                height - 1 - scale(x, min_x, max_x, height),
+                scale(y, min_y, max_y, width),
+            )
+        };
+
+        let mut grid = vec![vec!['.'; width]; height];
+        for pair in self.positions.windows(2) {
+            let (from, to) = (to_cell(pair[0].x, pair[0].y), to_cell(pair[1].x, pair[1].y));
+            let steps = from.0.abs_diff(to.0).max(from.1.abs_diff(to.1)).max(1);
+
+            for i in 0..=steps {
+                let t = i as f64 / steps as f64;
+                let row = from.0 as f64 + (to.0 as f64 - from.0 as f64) * t;
+                let col = from.1 as f64 + (to.1 as f64 - from.1 as f64) * t;
+                grid[row.round() as usize][col.round() as usize] = '#';
+            }
+        }
+
+        let start = self.positions[0];
+        let end = self.positions[self.positions.len() - 1];
+        let (end_row, end_col) = to_cell(end.x, end.y);
+        grid[end_row][end_col] = 'E';
+        let (start_row, start_col) = to_cell(start.x, start.y);
+        grid[start_row][start_col] = 'S';
+
+        grid.into_iter()
+            .map(|row| row.into_iter().collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// A ferry that can turn and rotate by any angle. It sails an exact
+/// `FerryTracker` until the first instruction that isn't a whole number or a
+/// multiple of 90 degrees, and floating point from then on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum AngledFerry {
+    Exact(FerryTracker),
+    Approx(ApproxFerry),
+}
+
+/// Headings are degrees clockwise from north, so east is 90.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct ApproxFerry {
+    north: f64,
+    east: f64,
+    heading: f64,
+    way_north: f64,
+    way_east: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Distances {
+    manhattan: f64,
+    euclidean: f64,
+    /// The Manhattan distance without rounding, if the ferry never left
+    /// whole numbers and right angles
+    exact_manhattan: Option<usize>,
+}
+
+/// A navigation instruction whose turns can be any angle and whose values
+/// can be fractional
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum AngledInstruction {
+    /// Whole values and turns by a multiple of 90 degrees
+    Exact(NavInstruction),
+    /// An `N`, `S`, `E` or `W` move as a (north, east) offset
+    Move {
+        north: f64,
+        east: f64,
+    },
+    /// Clockwise degrees, so `L` turns are negative
+    Turn(f64),
+    Forward(f64),
+}
+
+impl AngledInstruction {
+    /// The same instruction with floating point values
+    fn approx(self) -> AngledInstruction {
+        let n_i = match self {
+            AngledInstruction::Exact(n_i) => n_i,
+            approx => return approx,
+        };
+
+        match n_i {
+            NavInstruction::Left(degrees) => AngledInstruction::Turn(-degrees as f64),
+            NavInstruction::Right(degrees) => AngledInstruction::Turn(degrees as f64),
+            NavInstruction::Forward(value) => AngledInstruction::Forward(value as f64),
+            compass => {
+                let (north, east) = compass_vector(compass);
+                AngledInstruction::Move {
+                    north: north as f64,
+                    east: east as f64,
+                }
+            }
+        }
+    }
+}
+
+impl AngledFerry {
+    fn new(way_north: isize, way_east: isize) -> AngledFerry {
+        AngledFerry::Exact(FerryTracker {
+            x: 0,
+            y: 0,
+            dir: EAST,
+            way_x: way_north,
+            way_y: way_east,
+        })
+    }
+
+    fn approx(self) -> ApproxFerry {
+        match self {
+            AngledFerry::Approx(ferry) => ferry,
+            AngledFerry::Exact(pos) => {
+                let heading =
+                    (0..4).find(|&q| rotate((1, 0), q * 90) == pos.dir).unwrap() as f64 * 90.0;
+
+                ApproxFerry {
+                    north: pos.x as f64,
+                    east: pos.y as f64,
+                    heading,
+                    way_north: pos.way_x as f64,
+                    way_east: pos.way_y as f64,
+                }
+            }
+        }
+    }
+
+    fn distances(&self) -> Distances {
+        match self {
+            AngledFerry::Exact(pos) => Distances {
+                manhattan: (pos.x.abs() + pos.y.abs()) as f64,
+                euclidean: (pos.x as f64).hypot(pos.y as f64),
+                exact_manhattan: Some(pos.x.unsigned_abs() + pos.y.unsigned_abs()),
+            },
+            AngledFerry::Approx(ferry) => Distances {
+                manhattan: ferry.north.abs() + ferry.east.abs(),
+                euclidean: ferry.north.hypot(ferry.east),
+                exact_manhattan: None,
+            },
+        }
+    }
+}
+
+/// Part1 rules, allowing any angle for `L`, `R` and the heading
+fn distance_traveled_angled(nav_instructions: Vec<String>) -> Distances {
+    nav_instructions
+        .iter()
+        .map(|n_i| parse_angled_instr(n_i))
+        .fold(AngledFerry::new(0, 0), navigate_angled_step)
+        .distances()
+}
+
+fn navigate_angled_step(ferry: AngledFerry, instruction: AngledInstruction) -> AngledFerry {
+    if let (AngledFerry::Exact(pos), AngledInstruction::Exact(n_i)) = (ferry, instruction) {
+        return AngledFerry::Exact(navigate_step(pos, n_i));
+    }
+
+    let ferry = ferry.approx();
+    AngledFerry::Approx(match instruction.approx() {
+        AngledInstruction::Move { north, east } => ApproxFerry {
+            north: ferry.north + north,
+            east: ferry.east + east,
+            ..ferry
+        },
+        AngledInstruction::Turn(degrees) => ApproxFerry {
+            heading: (ferry.heading + degrees).rem_euclid(360.0),
+            ..ferry
+        },
+        AngledInstruction::Forward(value) => {
+            let (north, east) = rotate_clockwise((value, 0.0), ferry.heading);
+            ApproxFerry {
+                north: ferry.north + north,
+                east: ferry.east + east,
+                ..ferry
+            }
+        }
+        AngledInstruction::Exact(_) => unreachable!("approx() converts exact instructions"),
+    })
+}
+
+/// Part2 rules, allowing any angle for rotating the waypoint
+fn distance_traveled_waypoint_angled(nav_instructions: Vec<String>) -> Distances {
+    nav_instructions
+        .iter()
+        .map(|n_i| parse_angled_instr(n_i))
+        .fold(AngledFerry::new(1, 10), navigate_waypoint_angled_step)
+        .distances()
+}
+
+fn navigate_waypoint_angled_step(
+    ferry: AngledFerry,
+    instruction: AngledInstruction,
+) -> AngledFerry {
+    if let (AngledFerry::Exact(pos), AngledInstruction::Exact(n_i)) = (ferry, instruction) {
+        return AngledFerry::Exact(navigate_waypoint_step(pos, n_i));
+    }
+
+    let ferry = ferry.approx();
+    AngledFerry::Approx(match instruction.approx() {
+        AngledInstruction::Move { north, east } => ApproxFerry {
+            way_north: ferry.way_north + north,
+            way_east: ferry.way_east + east,
+            ..ferry
+        },
+        AngledInstruction::Turn(degrees) => {
+            let (way_north, way_east) =
+                rotate_clockwise((ferry.way_north, ferry.way_east), degrees);
+            ApproxFerry {
+                way_north,
+                way_east,
+                ..ferry
+            }
+        }
+        AngledInstruction::Forward(value) => ApproxFerry {
+            north: ferry.north + ferry.way_north * value,
+            east: ferry.east + ferry.way_east * value,
+            ..ferry
+        },
+        AngledInstruction::Exact(_) => unreachable!("approx() converts exact instructions"),
+    })
+}
+
+/// Rotates a (north, east) vector clockwise. Multiples of 90 degrees only
+/// swap and negate, so integer values stay exact.
+fn rotate_clockwise((north, east): (f64, f64), degrees: f64) -> (f64, f64) {
+    let degrees = degrees.rem_euclid(360.0);
 
-    if remaining_moves > 0 {
-        move_to_waypoint((instruction.0, remaining_moves), new_pos)
+    // A tiny negative angle wraps to exactly 360, so reduce the quarter
+    // turns again rather than trusting the range of rem_euclid
+    if degrees % 90.0 == 0.0 {
+        match (degrees / 90.0) as u32 % 4 {
+            0 => (north, east),
+            1 => (-east, north),
+            2 => (-north, -east),
+            _ => (east, -north),
+        }
     } else {
-        new_pos
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        (north * cos - east * sin, north * sin + east * cos)
     }
 }
 
-fn parse_nav_instr(nav_instruction: String) -> (char, isize) {
-    let (dir, value) = nav_instruction.split_at(1);
+fn parse_angled_instr(nav_instruction: &str) -> AngledInstruction {
+    nav_instruction.parse().expect(nav_instruction)
+}
 
-    (dir.chars().last().unwrap(), value.parse().unwrap())
+fn parse_nav_instr(nav_instruction: String) -> NavInstruction {
+    nav_instruction.parse().expect(&nav_instruction)
+}
+
+/// Parses an action character followed by its value, e.g. `F10` or `R90`
+impl FromStr for NavInstruction {
+    type Err = ParseNavError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let action = s.chars().next().ok_or(ParseNavError::Empty)?;
+        let raw_value = &s[action.len_utf8()..];
+        let value: isize = raw_value
+            .parse()
+            .map_err(|_| ParseNavError::InvalidValue(raw_value.to_string()))?;
+
+        if (action == 'L' || action == 'R') && value % 90 != 0 {
+            return Err(ParseNavError::InvalidAngle(value));
+        }
+
+        match action {
+            'N' => Ok(NavInstruction::North(value)),
+            'S' => Ok(NavInstruction::South(value)),
+            'E' => Ok(NavInstruction::East(value)),
+            'W' => Ok(NavInstruction::West(value)),
+            'L' => Ok(NavInstruction::Left(value)),
+            'R' => Ok(NavInstruction::Right(value)),
+            'F' => Ok(NavInstruction::Forward(value)),
+            _ => Err(ParseNavError::UnknownAction(action)),
+        }
+    }
+}
+
+impl fmt::Display for NavInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NavInstruction::North(value) => write!(f, "N{}", value),
+            NavInstruction::South(value) => write!(f, "S{}", value),
+            NavInstruction::East(value) => write!(f, "E{}", value),
+            NavInstruction::West(value) => write!(f, "W{}", value),
+            NavInstruction::Left(value) => write!(f, "L{}", value),
+            NavInstruction::Right(value) => write!(f, "R{}", value),
+            NavInstruction::Forward(value) => write!(f, "F{}", value),
+        }
+    }
+}
+
+/// Parses an action character followed by a decimal value, e.g. `F2.5` or
+/// `L45`. Anything `NavInstruction` can parse stays exact.
+impl FromStr for AngledInstruction {
+    type Err = ParseNavError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(n_i) = s.parse() {
+            return Ok(AngledInstruction::Exact(n_i));
+        }
+
+        let action = s.chars().next().ok_or(ParseNavError::Empty)?;
+        let raw_value = &s[action.len_utf8()..];
+        let value: f64 = raw_value
+            .parse()
+            .ok()
+            .filter(|value: &f64| value.is_finite())
+            .ok_or_else(|| ParseNavError::InvalidValue(raw_value.to_string()))?;
+
+        match action {
+            'N' => Ok(AngledInstruction::Move {
+                north: value,
+                east: 0.0,
+            }),
+            'S' => Ok(AngledInstruction::Move {
+                north: -value,
+                east: 0.0,
+            }),
+            'E' => Ok(AngledInstruction::Move {
+                north: 0.0,
+                east: value,
+            }),
+            'W' => Ok(AngledInstruction::Move {
+                north: 0.0,
+                east: -value,
+            }),
+            'L' => Ok(AngledInstruction::Turn(-value)),
+            'R' => Ok(AngledInstruction::Turn(value)),
+            'F' => Ok(AngledInstruction::Forward(value)),
+            _ => Err(ParseNavError::UnknownAction(action)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -221,6 +835,325 @@ F11";
         assert_eq!(actual, expected);
     }
 
+    fn example_route(step: fn(FerryTracker, NavInstruction) -> FerryTracker) -> Route {
+        let nav = EXAMPLE_NAV_INSTRUCTIONS
+            .lines()
+            .map(|l| parse_nav_instr(l.to_string()))
+            .collect();
+        let start = FerryTracker {
+            x: 0,
+            y: 0,
+            dir: EAST,
+            way_x: 1,
+            way_y: 10,
+        };
+
+        record_route(nav, start, step)
+    }
+
+    #[test]
+    fn record_route_example() {
+        let route = example_route(navigate_step);
+        let ship: Vec<(isize, isize)> = route.positions.iter().map(|p| (p.x, p.y)).collect();
+
+        assert_eq!(
+            ship,
+            vec![(0, 0), (0, 10), (3, 10), (3, 17), (3, 17), (-8, 17)]
+        );
+        assert_eq!(route.positions[4].dir, (-1, 0));
+        assert_eq!(route.bounding_box(), ((-8, 0), (3, 17)));
+        assert_eq!(route.furthest_point(), (-8, 17));
+        assert_eq!(route.path_length(), 31.0);
+    }
+
+    #[test]
+    fn record_route_waypoint_example() {
+        let route = example_route(navigate_waypoint_step);
+        let ship: Vec<(isize, isize)> = route.positions.iter().map(|p| (p.x, p.y)).collect();
+        let waypoints: Vec<(isize, isize)> =
+            route.positions.iter().map(|p| (p.way_x, p.way_y)).collect();
+
+        assert_eq!(
+            ship,
+            vec![
+                (0, 0),
+                (10, 100),
+                (10, 100),
+                (38, 170),
+                (38, 170),
+                (-72, 214)
+            ]
+        );
+        assert_eq!(
+            waypoints,
+            vec![(1, 10), (1, 10), (4, 10), (4, 10), (-10, 4), (-10, 4)]
+        );
+        assert_eq!(route.furthest_point(), (-72, 214));
+    }
+
+    #[test]
+    fn route_to_ascii_example() {
+        let expected = "\
+..........########
+..........#......#
+..........#......#
+S##########......#
+.................#
+.................#
+.................#
+.................#
+.................#
+.................#
+.................#
+.................E";
+        let route = example_route(navigate_step);
+        let actual = route.to_ascii(18, 12);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn route_to_ascii_empty_grid() {
+        let route = record_route(vec![NavInstruction::Forward(3)], PART1_START, navigate_step);
+
+        assert_eq!(route.to_ascii(0, 5), "");
+        assert_eq!(route.to_ascii(5, 0), "");
+        assert_eq!(route.to_ascii(1, 1), "S");
+    }
+
+    #[test]
+    fn route_to_svg_example() {
+        let route = example_route(navigate_step);
+        let actual = route.to_svg();
+
+        assert!(actual
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-1 -4 19 13\">"));
+        assert!(actual.contains("points=\"0,0 10,0 10,-3 17,-3 17,-3 17,8\""));
+        assert!(actual.ends_with("</svg>"));
+    }
+
+    #[test]
+    fn parse_nav_instr_test() {
+        assert_eq!("F10".parse(), Ok(NavInstruction::Forward(10)));
+        assert_eq!("L270".parse(), Ok(NavInstruction::Left(270)));
+        assert_eq!("W-3".parse(), Ok(NavInstruction::West(-3)));
+        assert_eq!("".parse::<NavInstruction>(), Err(ParseNavError::Empty));
+        assert_eq!(
+            "X5".parse::<NavInstruction>(),
+            Err(ParseNavError::UnknownAction('X'))
+        );
+        assert_eq!(
+            "Nx".parse::<NavInstruction>(),
+            Err(ParseNavError::InvalidValue("x".to_string()))
+        );
+        assert_eq!(
+            "R45".parse::<NavInstruction>(),
+            Err(ParseNavError::InvalidAngle(45))
+        );
+    }
+
+    #[test]
+    fn rotate_test() {
+        assert_eq!(rotate((1, 10), 90), (-10, 1));
+        assert_eq!(rotate((1, 10), -90), (10, -1));
+        assert_eq!(rotate((1, 10), 180), (-1, -10));
+        assert_eq!(rotate((1, 10), 270), rotate((1, 10), -90));
+        assert_eq!(rotate((1, 10), 720), (1, 10));
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't a multiple of 90")]
+    fn rotate_partial_turn() {
+        rotate((1, 10), 45);
+    }
+
+    static PART1_START: FerryTracker = FerryTracker {
+        x: 0,
+        y: 0,
+        dir: EAST,
+        way_x: 0,
+        way_y: 0,
+    };
+
+    static PART2_START: FerryTracker = FerryTracker {
+        x: 0,
+        y: 0,
+        dir: EAST,
+        way_x: 1,
+        way_y: 10,
+    };
+
+    #[test]
+    fn synthesize_direct_test() {
+        let expected = vec![
+            NavInstruction::Right(90),
+            NavInstruction::Forward(8),
+            NavInstruction::East(17),
+        ];
+        let actual = synthesize_direct(PART1_START, 17, -8, (-1, 0)).unwrap();
+
+        assert_eq!(actual, expected);
+        assert_eq!(format_nav_instrs(&actual), vec!["R90", "F8", "E17"]);
+        assert_eq!(distance_traveled(format_nav_instrs(&actual)), 25);
+    }
+
+    #[test]
+    fn synthesize_direct_every_heading() {
+        let headings = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+        for &heading in &headings {
+            for &(east, north) in &[(0, 0), (5, 0), (-5, 3), (0, -7), (12, 12)] {
+                let actual = synthesize_direct(PART1_START, east, north, heading).unwrap();
+
+                assert!(actual.len() <= 3);
+            }
+        }
+        assert_eq!(
+            synthesize_direct(PART1_START, 1, 1, (1, 1)),
+            Err(SynthesisError::InvalidHeading((1, 1)))
+        );
+    }
+
+    #[test]
+    fn synthesize_waypoint_test() {
+        let expected = vec![NavInstruction::Forward(7), NavInstruction::Left(90)];
+        let actual = synthesize_waypoint(PART2_START, 70, 7, (10, -1)).unwrap();
+
+        assert_eq!(actual, expected);
+
+        let expected = vec![
+            NavInstruction::Right(180),
+            NavInstruction::Forward(3),
+            NavInstruction::North(1),
+            NavInstruction::East(12),
+        ];
+        let actual = synthesize_waypoint(PART2_START, -30, -3, (0, 2)).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn synthesize_waypoint_fixtures() {
+        for &(east, north) in &[(0, 0), (214, -72), (-5, 3), (0, -7), (1000, 1)] {
+            let actual = synthesize_waypoint(PART2_START, east, north, (1, 10)).unwrap();
+            let distance = distance_traveled_waypoint(format_nav_instrs(&actual));
+
+            assert_eq!(distance, (east.abs() + north.abs()) as usize);
+        }
+    }
+
+    #[test]
+    fn navigate_large_values() {
+        let start = FerryTracker {
+            x: 0,
+            y: 0,
+            dir: EAST,
+            way_x: 1,
+            way_y: 10,
+        };
+        let nav = vec![
+            NavInstruction::Forward(100_000_000),
+            NavInstruction::Left(90_000),
+            NavInstruction::Forward(1),
+        ];
+        let direct = navigate(nav.clone(), start);
+        let waypoint = navigate_waypoint(nav, start);
+
+        assert_eq!((direct.x, direct.y), (0, 100_000_001));
+        assert_eq!((waypoint.x, waypoint.y), (100_000_001, 1_000_000_010));
+    }
+
+    #[test]
+    fn distance_traveled_angled_matches_right_angles() {
+        let nav = load_as_vec_string("day12");
+        let actual = distance_traveled_angled(nav.clone());
+        let actual_waypoint = distance_traveled_waypoint_angled(nav);
+
+        assert_eq!(actual.manhattan, 415.0);
+        assert_eq!(actual.exact_manhattan, Some(415));
+        assert_eq!(actual_waypoint.manhattan, 29401.0);
+        assert_eq!(actual_waypoint.exact_manhattan, Some(29401));
+    }
+
+    #[test]
+    fn distance_traveled_angled_example() {
+        let nav = vec!["L45".to_string(), "F10".to_string(), "R22.5".to_string()];
+        let actual = distance_traveled_angled(nav);
+
+        assert!((actual.euclidean - 10.0).abs() < 1e-9);
+        assert!((actual.manhattan - 200.0f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn distance_traveled_waypoint_angled_example() {
+        let nav = vec!["W10".to_string(), "R30".to_string(), "F2".to_string()];
+        let actual = distance_traveled_waypoint_angled(nav);
+
+        assert!((actual.euclidean - 2.0).abs() < 1e-9);
+        assert!((actual.manhattan - (1.0 + 3.0f64.sqrt())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parse_angled_instr_test() {
+        assert_eq!(
+            "W2.5".parse(),
+            Ok(AngledInstruction::Move {
+                north: 0.0,
+                east: -2.5
+            })
+        );
+        assert_eq!("L22.5".parse(), Ok(AngledInstruction::Turn(-22.5)));
+        assert_eq!("".parse::<AngledInstruction>(), Err(ParseNavError::Empty));
+        assert_eq!(
+            "é1".parse::<AngledInstruction>(),
+            Err(ParseNavError::UnknownAction('é'))
+        );
+        assert_eq!(
+            "Finf".parse::<AngledInstruction>(),
+            Err(ParseNavError::InvalidValue("inf".to_string()))
+        );
+        assert_eq!(
+            "R".parse::<AngledInstruction>(),
+            Err(ParseNavError::InvalidValue("".to_string()))
+        );
+    }
+
+    #[test]
+    fn distance_traveled_angled_stays_exact() {
+        // 2^53 + 1 can't be represented as an f64
+        let nav = vec![
+            "F9007199254740993".to_string(),
+            "R90".to_string(),
+            "F1".to_string(),
+        ];
+        let actual = distance_traveled_angled(nav);
+
+        assert_eq!(actual.exact_manhattan, Some(9007199254740994));
+
+        let nav = vec!["F1".to_string(), "R45".to_string(), "L45".to_string()];
+        assert_eq!(distance_traveled_angled(nav).exact_manhattan, None);
+    }
+
+    #[test]
+    fn distance_traveled_waypoint_angled_tiny_turn() {
+        let nav = vec!["L0.00000000000001".to_string(), "F1".to_string()];
+        let actual = distance_traveled_waypoint_angled(nav);
+
+        assert!((actual.manhattan - 11.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rotate_clockwise_test() {
+        assert_eq!(rotate_clockwise((1.0, 10.0), 90.0), (-10.0, 1.0));
+        assert_eq!(rotate_clockwise((1.0, 10.0), -90.0), (10.0, -1.0));
+        assert_eq!(rotate_clockwise((1.0, 10.0), 540.0), (-1.0, -10.0));
+        assert_eq!(rotate_clockwise((1.0, 10.0), -1e-14), (1.0, 10.0));
+
+        let (north, east) = rotate_clockwise((2.0, 0.0), 60.0);
+        assert!((north - 1.0).abs() < 1e-9);
+        assert!((east - 3.0f64.sqrt()).abs() < 1e-9);
+    }
+
     /// Part2
     #[test]
     fn distance_traveled_waypoint_from_input() {
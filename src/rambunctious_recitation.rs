@@ -1,26 +1,351 @@
-use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::mem;
 
 fn memory_game(starting_numbers: Vec<usize>, final_turn: usize) -> usize {
-    let mut memory: HashMap<usize, usize> = HashMap::new();
-    let mut prev_n: usize = 0;
+    let starting: Vec<u32> = starting_numbers.iter().map(|&n| n as u32).collect();
 
-    for i in 0..starting_numbers.len() {
-        let n = starting_numbers[i];
-        memory.insert(n, i + 1);
+    spoken_on_turn(&starting, final_turn as u32) as usize
+}
+
+/// What gets said once the starting numbers run out
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+struct GameRules {
+    /// Say how many turns ago the last number was spoken for the
+    /// `lookback`-th time before that
+    lookback: u32,
+    /// Said when the last number hasn't been spoken `lookback` times before
+    default: u32,
+    /// Every spoken number is reduced modulo this, if set
+    modulus: Option<u32>,
+}
+
+impl GameRules {
+    /// The rules from the puzzle
+    const STANDARD: GameRules = GameRules {
+        lookback: 1,
+        default: 0,
+        modulus: None,
+    };
+
+    fn cap(&self, n: u32) -> u32 {
+        match self.modulus {
+            Some(modulus) => n % modulus,
+            None => n,
+        }
+    }
+}
+
+/// Plays the memory game one turn per `next`, yielding each spoken number.
+/// The turns each number was last spoken on are kept in a `Vec` indexed by
+/// the number, so they never need hashing.
+#[derive(PartialEq, Debug, Clone)]
+struct MemoryGame {
+    starting: Vec<u32>,
+    rules: GameRules,
+    /// `rules.lookback` slots per number, holding the most recent turns it
+    /// was spoken on first, not counting `prev`'s latest turn. 0 means never.
+    last_turns: Vec<u32>,
+    /// The number of turns played so far
+    turn: u32,
+    /// The number spoken on `turn`
+    prev: u32,
+    /// How many different numbers have been spoken so far
+    distinct: u32,
+}
+
+#[derive(Debug)]
+enum CheckpointError {
+    Io(io::Error),
+    /// The data doesn't start with `CHECKPOINT_MAGIC`
+    NotACheckpoint,
+    /// The data is a checkpoint, but its fields don't describe a valid game
+    Corrupt(&'static str),
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"MEMGAME1";
+
+impl MemoryGame {
+    fn new(starting: &[u32]) -> MemoryGame {
+        MemoryGame::with_capacity(starting, 0)
+    }
+
+    /// Allocates enough memory up front to play `turns` turns without growing
+    fn with_capacity(starting: &[u32], turns: u32) -> MemoryGame {
+        MemoryGame::with_rules(starting, GameRules::STANDARD, turns)
     }
 
-    for turn in starting_numbers.len() + 1..final_turn {
-        let prev_turn = memory.get_mut(&prev_n);
-        if let Some(prev_turn) = prev_turn {
-            prev_n = turn - *prev_turn;
-            *prev_turn = turn;
+    /// A game that follows `rules` once the starting numbers run out, with
+    /// room for the numbers spoken in `turns` turns
+    fn with_rules(starting: &[u32], rules: GameRules, turns: u32) -> MemoryGame {
+        assert!(rules.lookback > 0, "lookback must be at least 1");
+        assert!(rules.modulus != Some(0), "modulus must be positive");
+
+        let starting: Vec<u32> = starting.iter().map(|&n| rules.cap(n)).collect();
+        let largest = starting.iter().max().map_or(0, |&n| n as usize + 1);
+        let numbers = match rules.modulus {
+            Some(modulus) => (turns as usize).min(modulus as usize),
+            None => turns as usize,
+        };
+
+        MemoryGame {
+            starting,
+            rules,
+            last_turns: vec![0; largest.max(numbers) * rules.lookback as usize],
+            turn: 0,
+            prev: 0,
+            distinct: 0,
+        }
+    }
+
+    fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    fn distinct(&self) -> u32 {
+        self.distinct
+    }
+
+    /// Plays until `turn` and returns the number spoken on it
+    fn advance_to(&mut self, turn: u32) -> Option<u32> {
+        if self.rules != GameRules::STANDARD {
+            while self.turn < turn {
+                self.next()?;
+            }
+        }
+
+        while self.turn < turn && (self.turn as usize) < self.starting.len() {
+            self.next()?;
+        }
+
+        if self.turn < turn {
+            // Every number spoken from here on is a gap between two earlier
+            // turns, so it's below `turn` and always has a slot
+            if self.last_turns.len() < turn as usize {
+                self.last_turns.resize(turn as usize, 0);
+            }
+
+            let mut prev = self.prev;
+            for current in self.turn..turn {
+                let last = mem::replace(&mut self.last_turns[prev as usize], current);
+                prev = if last == 0 { 0 } else { current - last };
+                if self.last_turns[prev as usize] == 0 {
+                    self.distinct += 1;
+                }
+            }
+            self.prev = prev;
+            self.turn = turn;
+        }
+
+        if turn == self.turn && turn > 0 {
+            Some(self.prev)
         } else {
-            memory.insert(prev_n, turn);
-            prev_n = 0;
+            None
+        }
+    }
+
+    fn record(&mut self, n: u32, turn: u32) {
+        let lookback = self.rules.lookback as usize;
+        let start = n as usize * lookback;
+        if start + lookback > self.last_turns.len() {
+            let len = (start + lookback).max(self.last_turns.len() * 2);
+            self.last_turns.resize(len, 0);
+        }
+
+        let slots = &mut self.last_turns[start..start + lookback];
+        slots.rotate_right(1);
+        slots[0] = turn;
+    }
+
+    /// The `back`-th most recent turn `n` was spoken on, counting from 0
+    fn last_turn(&self, n: u32, back: u32) -> u32 {
+        let i = n as usize * self.rules.lookback as usize + back as usize;
+
+        self.last_turns.get(i).copied().unwrap_or(0)
+    }
+
+    /// Writes everything needed to resume the game with `load`
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(CHECKPOINT_MAGIC)?;
+
+        let modulus = self.rules.modulus.unwrap_or(0);
+        let header = [
+            self.rules.lookback,
+            self.rules.default,
+            modulus,
+            self.turn,
+            self.prev,
+            self.distinct,
+        ];
+        write_u32s(writer, &header)?;
+
+        for values in [&self.starting, &self.last_turns].iter() {
+            writer.write_all(&(values.len() as u64).to_le_bytes())?;
+            write_u32s(writer, values)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds a game written by `save`, ready to keep playing
+    fn load<R: Read>(reader: &mut R) -> Result<MemoryGame, CheckpointError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(CheckpointError::NotACheckpoint);
+        }
+
+        let mut header = [0; 6];
+        read_u32s_into(reader, &mut header)?;
+        let [lookback, default, modulus, turn, prev, distinct] = header;
+        if lookback == 0 {
+            return Err(CheckpointError::Corrupt("lookback is 0"));
+        }
+
+        let starting = read_u32s(reader)?;
+        let last_turns = read_u32s(reader)?;
+        if last_turns.len() % lookback as usize != 0 {
+            return Err(CheckpointError::Corrupt("memory doesn't fit the lookback"));
+        }
+        if last_turns.iter().any(|&last| last > turn) {
+            return Err(CheckpointError::Corrupt("memory has an unplayed turn"));
+        }
+        if distinct > turn {
+            return Err(CheckpointError::Corrupt("more distinct numbers than turns"));
+        }
+
+        let rules = GameRules {
+            lookback,
+            default,
+            modulus: if modulus == 0 { None } else { Some(modulus) },
+        };
+        // `prev` gets a slot before it's looked up as long as it's one of the
+        // starting numbers, a gap between earlier turns or the default
+        let prev_valid = match turn as usize {
+            0 => prev == 0,
+            t if t <= starting.len() => prev == starting[t - 1],
+            _ => prev < turn || prev == rules.cap(default),
+        };
+        if !prev_valid {
+            return Err(CheckpointError::Corrupt("prev doesn't fit the turn"));
+        }
+
+        Ok(MemoryGame {
+            starting,
+            rules,
+            last_turns,
+            turn,
+            prev,
+            distinct,
+        })
+    }
+}
+
+/// Values per read or write, so checkpoints move in large blocks instead of
+/// one call per value
+const CHECKPOINT_CHUNK: usize = 1 << 16;
+
+fn write_u32s<W: Write>(writer: &mut W, values: &[u32]) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(values.len().min(CHECKPOINT_CHUNK) * 4);
+    for chunk in values.chunks(CHECKPOINT_CHUNK) {
+        bytes.clear();
+        for value in chunk {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        writer.write_all(&bytes)?;
+    }
+
+    Ok(())
+}
+
+fn read_u32s_into<R: Read>(reader: &mut R, values: &mut [u32]) -> io::Result<()> {
+    let mut bytes = vec![0; values.len().min(CHECKPOINT_CHUNK) * 4];
+    for chunk in values.chunks_mut(CHECKPOINT_CHUNK) {
+        let bytes = &mut bytes[..chunk.len() * 4];
+        reader.read_exact(bytes)?;
+        for (value, raw) in chunk.iter_mut().zip(bytes.chunks_exact(4)) {
+            *value = u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a length followed by that many values. The values are read a chunk
+/// at a time, so a corrupt length fails at the end of the data rather than
+/// allocating it all up front.
+fn read_u32s<R: Read>(reader: &mut R) -> Result<Vec<u32>, CheckpointError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    let len = usize::try_from(u64::from_le_bytes(bytes))
+        .map_err(|_| CheckpointError::Corrupt("length doesn't fit in memory"))?;
+
+    let mut values = Vec::new();
+    while values.len() < len {
+        let start = values.len();
+        values.resize(start + (len - start).min(CHECKPOINT_CHUNK), 0);
+        read_u32s_into(reader, &mut values[start..])?;
+    }
+
+    Ok(values)
+}
+
+impl Iterator for MemoryGame {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.turn == u32::MAX {
+            return None;
         }
+
+        let spoken = match self.starting.get(self.turn as usize) {
+            Some(&n) => n,
+            None => match self.last_turn(self.prev, self.rules.lookback - 1) {
+                0 => self.rules.cap(self.rules.default),
+                last => self.rules.cap(self.turn - last),
+            },
+        };
+
+        if self.turn > 0 {
+            self.record(self.prev, self.turn);
+        }
+        if self.last_turn(spoken, 0) == 0 {
+            self.distinct += 1;
+        }
+        self.prev = spoken;
+        self.turn += 1;
+
+        Some(spoken)
     }
+}
 
-    prev_n
+/// The number spoken on `turn`, counting from 1
+fn spoken_on_turn(starting: &[u32], turn: u32) -> u32 {
+    MemoryGame::with_capacity(starting, turn)
+        .advance_to(turn)
+        .expect("turns start at 1")
+}
+
+/// The first turn `n` is spoken on, looking no further than `max_turn`
+fn first_turn_spoken(starting: &[u32], n: u32, max_turn: u32) -> Option<u32> {
+    MemoryGame::with_capacity(starting, max_turn)
+        .take(max_turn as usize)
+        .position(|spoken| spoken == n)
+        .map(|i| i as u32 + 1)
+}
+
+/// How many different numbers have been spoken by the end of `turn`
+fn distinct_by_turn(starting: &[u32], turn: u32) -> u32 {
+    let mut game = MemoryGame::with_capacity(starting, turn);
+    game.advance_to(turn);
+
+    game.distinct()
 }
 
 #[cfg(test)]
@@ -70,6 +395,190 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn memory_game_iterator_example() {
+        let expected = vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0];
+        let actual: Vec<u32> = MemoryGame::new(&[0, 3, 6]).take(10).collect();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn memory_game_repeated_starting_numbers() {
+        let expected = vec![1, 1, 2, 0, 0, 1, 4, 0, 3, 0];
+        let actual: Vec<u32> = MemoryGame::new(&[1, 1, 2]).take(10).collect();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn memory_game_queries() {
+        let starting = [0, 3, 6];
+
+        assert_eq!(spoken_on_turn(&starting, 9), 4);
+        assert_eq!(spoken_on_turn(&starting, 2020), 436);
+        assert_eq!(first_turn_spoken(&starting, 4, 100), Some(9));
+        assert_eq!(first_turn_spoken(&starting, 3, 100), Some(2));
+        assert_eq!(first_turn_spoken(&starting, 4, 8), None);
+        assert_eq!(distinct_by_turn(&starting, 10), 5);
+        assert_eq!(distinct_by_turn(&starting, 0), 0);
+    }
+
+    #[test]
+    fn memory_game_advance_to() {
+        let mut game = MemoryGame::new(&[0, 3, 6]);
+
+        assert_eq!(game.advance_to(0), None);
+        assert_eq!(game.advance_to(4), Some(0));
+        assert_eq!(game.advance_to(9), Some(4));
+        assert_eq!(game.advance_to(5), None);
+        assert_eq!(game.turn(), 9);
+    }
+
+    #[test]
+    fn memory_game_rules_standard_matches() {
+        let rules = GameRules::STANDARD;
+        let mut fast = MemoryGame::new(&[0, 3, 6]);
+        let stepped: Vec<u32> = MemoryGame::with_rules(&[0, 3, 6], rules, 0)
+            .take(2020)
+            .collect();
+
+        assert_eq!(fast.advance_to(2020), stepped.last().copied());
+    }
+
+    #[test]
+    fn memory_game_rules_lookback() {
+        let rules = GameRules {
+            lookback: 2,
+            default: 0,
+            modulus: None,
+        };
+        // 0 has only been spoken once before turn 4, so turn 5 says 0 again.
+        // By turn 6 it was spoken on turns 1, 4 and 5, so say 5 - 1
+        let expected = vec![0, 3, 6, 0, 0, 4, 0, 3, 0];
+        let actual: Vec<u32> = MemoryGame::with_rules(&[0, 3, 6], rules, 0)
+            .take(9)
+            .collect();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn memory_game_rules_default_and_modulus() {
+        let rules = GameRules {
+            lookback: 1,
+            default: 7,
+            modulus: Some(5),
+        };
+        // 7 caps to 2, and the starting 6 caps to 1
+        let expected = vec![0, 3, 1, 2, 2, 1, 3, 0, 2, 4];
+        let actual: Vec<u32> = MemoryGame::with_rules(&[0, 3, 6], rules, 0)
+            .take(10)
+            .collect();
+
+        assert_eq!(actual, expected);
+        assert!(MemoryGame::with_rules(&[0, 3, 6], rules, 0)
+            .take(1000)
+            .all(|n| n < 5));
+    }
+
+    #[test]
+    fn memory_game_checkpoint_resume() {
+        let rules = GameRules {
+            lookback: 3,
+            default: 1,
+            modulus: Some(1000),
+        };
+        let expected: Vec<u32> = MemoryGame::with_rules(&[2, 0, 1], rules, 0)
+            .skip(500)
+            .take(500)
+            .collect();
+
+        let mut game = MemoryGame::with_rules(&[2, 0, 1], rules, 0);
+        game.advance_to(500);
+        let mut checkpoint = vec![];
+        game.save(&mut checkpoint).unwrap();
+        let resumed = MemoryGame::load(&mut checkpoint.as_slice()).unwrap();
+
+        assert_eq!(resumed, game);
+        assert_eq!(resumed.take(500).collect::<Vec<u32>>(), expected);
+        assert!(matches!(
+            MemoryGame::load(&mut &b"NOTAGAME"[..]),
+            Err(CheckpointError::NotACheckpoint)
+        ));
+        assert!(matches!(
+            MemoryGame::load(&mut &checkpoint[..20]),
+            Err(CheckpointError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn memory_game_checkpoint_corrupt() {
+        let mut game = MemoryGame::new(&[0, 3, 6]);
+        game.advance_to(10);
+        let mut checkpoint = vec![];
+        game.save(&mut checkpoint).unwrap();
+        let patched = |offset: usize, value: u32| {
+            let mut patched = checkpoint.clone();
+            patched[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            MemoryGame::load(&mut patched.as_slice())
+        };
+        // Header fields follow the magic, then the starting numbers and the
+        // memory each follow their length
+        let prev_offset = 8 + 4 * 4;
+        let distinct_offset = 8 + 5 * 4;
+        let memory_offset = 8 + 6 * 4 + 8 + 3 * 4 + 8;
+
+        assert!(patched(prev_offset, 6).is_ok());
+        assert!(matches!(
+            patched(prev_offset, 1_000_000),
+            Err(CheckpointError::Corrupt(_))
+        ));
+        assert!(matches!(
+            patched(distinct_offset, 11),
+            Err(CheckpointError::Corrupt(_))
+        ));
+        assert!(matches!(
+            patched(memory_offset, 11),
+            Err(CheckpointError::Corrupt(_))
+        ));
+    }
+
+    /// Counts calls to `write`, to check checkpoints aren't written a value
+    /// at a time
+    struct CountingWriter {
+        bytes: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn memory_game_checkpoint_writes_in_chunks() {
+        let mut game = MemoryGame::new(&[0, 3, 6]);
+        game.advance_to(200_000);
+        let mut writer = CountingWriter {
+            bytes: vec![],
+            writes: 0,
+        };
+        game.save(&mut writer).unwrap();
+
+        assert!(writer.writes < 20, "{} writes", writer.writes);
+        assert_eq!(
+            MemoryGame::load(&mut writer.bytes.as_slice()).unwrap(),
+            game
+        );
+    }
+
     #[test]
     fn memory_game_30000000_example_1() {
         let expected = 175594;
@@ -1,8 +1,111 @@
-#[derive(Clone)]
+use colored::Colorize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::Duration;
+use std::{fs, io, mem, thread};
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 struct SeatLayout {
     layout: Vec<Vec<char>>,
 }
 
+#[derive(PartialEq, Debug)]
+enum SimulationEnd {
+    /// The layout at this generation is the same as the one after it
+    FixedPoint { generation: usize },
+    /// The layout first seen at generation `start` repeats every `period` generations
+    Cycle { start: usize, period: usize },
+}
+
+#[derive(PartialEq, Debug)]
+struct Simulation {
+    /// The fixed point, or the layout where the cycle was detected
+    layout: SeatLayout,
+    end: SimulationEnd,
+}
+
+/// The characters used for each kind of cell
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct CellAlphabet {
+    empty: char,
+    occupied: char,
+    floor: char,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Neighborhood {
+    /// The 8 surrounding cells
+    Adjacent,
+    /// The first seat seen in each of the 8 directions, looking past floor
+    LineOfSight,
+    /// Every cell within `k` steps, counting diagonal steps as one
+    Radius(usize),
+    /// The 4 orthogonally adjacent cells
+    VonNeumann,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum EdgePolicy {
+    /// Cells past the edge don't exist
+    Bounded,
+    /// The layout wraps around, so the last row neighbors the first
+    Toroidal,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct SeatRules {
+    alphabet: CellAlphabet,
+    /// An empty seat is filled when at most this many neighbors are occupied
+    birth_limit: usize,
+    /// An occupied seat is emptied when at least this many neighbors are occupied
+    death_limit: usize,
+    neighborhood: Neighborhood,
+    edges: EdgePolicy,
+}
+
+const AOC_ALPHABET: CellAlphabet = CellAlphabet {
+    empty: 'L',
+    occupied: '#',
+    floor: '.',
+};
+
+impl SeatRules {
+    /// Part1 rules
+    fn adjacent() -> SeatRules {
+        SeatRules {
+            alphabet: AOC_ALPHABET,
+            birth_limit: 0,
+            death_limit: 4,
+            neighborhood: Neighborhood::Adjacent,
+            edges: EdgePolicy::Bounded,
+        }
+    }
+
+    /// Part2 rules
+    fn line_of_sight() -> SeatRules {
+        SeatRules {
+            alphabet: AOC_ALPHABET,
+            birth_limit: 0,
+            death_limit: 5,
+            neighborhood: Neighborhood::LineOfSight,
+            edges: EdgePolicy::Bounded,
+        }
+    }
+}
+
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
 impl SeatLayout {
     fn new(layout: Vec<String>) -> SeatLayout {
         SeatLayout {
@@ -14,63 +117,181 @@ impl SeatLayout {
         self.layout[x][y]
     }
 
-    fn get_checked(&self, x: isize, y: isize) -> Option<char> {
-        if x < 0 || x >= self.len_x() as _ || y < 0 || y >= self.len_y() as _ {
-            return None;
+    /// Maps a position that may be past the edge back onto the layout
+    fn resolve(&self, x: isize, y: isize, edges: EdgePolicy) -> Option<(usize, usize)> {
+        match edges {
+            EdgePolicy::Bounded => {
+                if x < 0 || x >= self.len_x() as _ || y < 0 || y >= self.len_y() as _ {
+                    None
+                } else {
+                    Some((x as usize, y as usize))
+                }
+            }
+            EdgePolicy::Toroidal => Some((
+                x.rem_euclid(self.len_x() as isize) as usize,
+                y.rem_euclid(self.len_y() as isize) as usize,
+            )),
         }
+    }
 
-        Some(self.layout[x as usize][y as usize])
-    }
-
-    fn get_adjacent(&self, x: usize, y: usize) -> Vec<Option<char>> {
-        vec![
-            self.get_checked(x as isize - 1, y as isize - 1),
-            self.get_checked(x as isize - 1, y as isize),
-            self.get_checked(x as isize - 1, y as isize + 1),
-            self.get_checked(x as isize, y as isize - 1),
-            self.get_checked(x as isize, y as isize + 1),
-            self.get_checked(x as isize + 1, y as isize - 1),
-            self.get_checked(x as isize + 1, y as isize),
-            self.get_checked(x as isize + 1, y as isize + 1),
-        ]
-    }
-
-    fn get_adjacent_vis(&self, x: usize, y: usize) -> Vec<Option<char>> {
-        vec![
-            self.get_path(x, y, (-1, -1)),
-            self.get_path(x, y, (-1, 0)),
-            self.get_path(x, y, (-1, 1)),
-            self.get_path(x, y, (0, -1)),
-            self.get_path(x, y, (0, 1)),
-            self.get_path(x, y, (1, -1)),
-            self.get_path(x, y, (1, 0)),
-            self.get_path(x, y, (1, 1)),
-        ]
-    }
-
-    fn get_path(&self, x: usize, y: usize, path: (isize, isize)) -> Option<char> {
-        let x2 = x as isize + path.0;
-        let y2 = y as isize + path.1;
-        let got = self.get_checked(x2, y2);
-
-        match got {
-            None => None,
-            Some('.') => self.get_path(x2 as usize, y2 as usize, path),
-            _ => got,
+    /// Positions of the cells that count as neighbors of (x, y) under `rules`
+    fn neighbor_positions(&self, x: usize, y: usize, rules: &SeatRules) -> Vec<(usize, usize)> {
+        let offsets: Vec<(isize, isize)> = match rules.neighborhood {
+            Neighborhood::LineOfSight => {
+                let mut seats: Vec<(usize, usize)> = DIRECTIONS
+                    .iter()
+                    .filter_map(|&dir| self.first_seat(x, y, dir, rules))
+                    .collect();
+                dedup_neighbors(&mut seats, (x, y));
+
+                return seats;
+            }
+            Neighborhood::Adjacent => DIRECTIONS.to_vec(),
+            Neighborhood::Radius(k) => {
+                let k = k as isize;
+                (-k..=k)
+                    .flat_map(|dx| (-k..=k).map(move |dy| (dx, dy)))
+                    .filter(|&offset| offset != (0, 0))
+                    .collect()
+            }
+            Neighborhood::VonNeumann => vec![(-1, 0), (0, -1), (0, 1), (1, 0)],
+        };
+
+        let mut positions: Vec<(usize, usize)> = offsets
+            .into_iter()
+            .filter_map(|(dx, dy)| self.resolve(x as isize + dx, y as isize + dy, rules.edges))
+            .collect();
+        dedup_neighbors(&mut positions, (x, y));
+
+        positions
+    }
+
+    /// Follows `dir` from (x, y) past any floor until reaching a seat. On a
+    /// toroidal layout the ray gives up once it wraps back around to (x, y).
+    fn first_seat(
+        &self,
+        x: usize,
+        y: usize,
+        dir: (isize, isize),
+        rules: &SeatRules,
+    ) -> Option<(usize, usize)> {
+        (1..)
+            .map(|step| {
+                self.resolve(
+                    x as isize + dir.0 * step,
+                    y as isize + dir.1 * step,
+                    rules.edges,
+                )
+            })
+            .take_while(|pos| pos.is_some() && *pos != Some((x, y)))
+            .flatten()
+            .find(|&(x2, y2)| self.get(x2, y2) != rules.alphabet.floor)
+    }
+
+    /// The cell at (x, y) after one generation
+    fn next_cell(&self, x: usize, y: usize, graph: &VisibilityGraph, rules: &SeatRules) -> char {
+        let c = self.get(x, y);
+        let occ_count = graph
+            .neighbors(x, y)
+            .iter()
+            .filter(|&&i| {
+                let (x2, y2) = graph.position(i);
+                self.get(x2, y2) == rules.alphabet.occupied
+            })
+            .count();
+
+        if c == rules.alphabet.empty && occ_count <= rules.birth_limit {
+            rules.alphabet.occupied
+        } else if c == rules.alphabet.occupied && occ_count >= rules.death_limit {
+            rules.alphabet.empty
+        } else {
+            c
         }
     }
 
-    fn count_total_occupied(&self) -> usize {
+    /// The whole layout after one generation
+    fn step(&self, graph: &VisibilityGraph, rules: &SeatRules) -> SeatLayout {
+        let mut new_layout = self.clone();
+        self.step_into(&mut new_layout, graph, rules, 1);
+
+        new_layout
+    }
+
+    /// Writes the next generation into `next`, which must be the same size.
+    /// With more than one thread, each thread fills its own band of rows.
+    fn step_into(
+        &self,
+        next: &mut SeatLayout,
+        graph: &VisibilityGraph,
+        rules: &SeatRules,
+        threads: usize,
+    ) {
+        let fill_rows = |first_x: usize, rows: &mut [Vec<char>]| {
+            for (i, row) in rows.iter_mut().enumerate() {
+                for (y, cell) in row.iter_mut().enumerate() {
+                    *cell = self.next_cell(first_x + i, y, graph, rules);
+                }
+            }
+        };
+
+        if threads <= 1 {
+            fill_rows(0, &mut next.layout);
+            return;
+        }
+
+        let band = self.len_x().div_ceil(threads);
+        thread::scope(|scope| {
+            for (band_i, rows) in next.layout.chunks_mut(band.max(1)).enumerate() {
+                scope.spawn(move || fill_rows(band_i * band, rows));
+            }
+        });
+    }
+
+    fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn count_cells(&self, cell: char) -> usize {
         self.layout
             .iter()
-            .map(|x| {
-                x.iter()
-                    .map(|&y| if y == '#' { 1 } else { 0 })
-                    .sum::<usize>()
-            })
+            .map(|x| x.iter().filter(|&&y| y == cell).count())
             .sum()
     }
 
+    fn to_text(&self) -> String {
+        self.layout
+            .iter()
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
+
+    /// Draws floor dark grey, empty seats green and occupied seats red. Any
+    /// other character is drawn white.
+    fn to_ppm(&self, rules: &SeatRules, scale: usize) -> Vec<u8> {
+        let (width, height) = (self.len_y() * scale, self.len_x() * scale);
+        let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+
+        for x in 0..height {
+            for y in 0..width {
+                let c = self.get(x / scale, y / scale);
+                let rgb = if c == rules.alphabet.floor {
+                    [40, 40, 40]
+                } else if c == rules.alphabet.empty {
+                    [80, 170, 90]
+                } else if c == rules.alphabet.occupied {
+                    [220, 70, 60]
+                } else {
+                    [255, 255, 255]
+                };
+                ppm.extend_from_slice(&rgb);
+            }
+        }
+
+        ppm
+    }
+
     fn len_x(&self) -> usize {
         self.layout.len()
     }
@@ -80,69 +301,227 @@ impl SeatLayout {
     }
 }
 
+/// Every seat's neighbors under one set of rules, stored as an adjacency
+/// table over seat indices (`x * len_y + y`). Floor never changes, so the
+/// table stays valid for every generation of the layout it was built from.
+struct VisibilityGraph {
+    len_y: usize,
+    /// `targets[offsets[i]..offsets[i + 1]]` are the neighbors of seat `i`
+    offsets: Vec<usize>,
+    targets: Vec<usize>,
+}
+
+impl VisibilityGraph {
+    fn new(layout: &SeatLayout, rules: &SeatRules) -> VisibilityGraph {
+        let len_y = layout.len_y();
+        let mut offsets = vec![0];
+        let mut targets = vec![];
+
+        for x in 0..layout.len_x() {
+            for y in 0..len_y {
+                if layout.get(x, y) != rules.alphabet.floor {
+                    targets.extend(
+                        layout
+                            .neighbor_positions(x, y, rules)
+                            .into_iter()
+                            .map(|(x2, y2)| x2 * len_y + y2),
+                    );
+                }
+                offsets.push(targets.len());
+            }
+        }
+
+        VisibilityGraph {
+            len_y,
+            offsets,
+            targets,
+        }
+    }
+
+    fn neighbors(&self, x: usize, y: usize) -> &[usize] {
+        let i = x * self.len_y + y;
+        &self.targets[self.offsets[i]..self.offsets[i + 1]]
+    }
+
+    fn position(&self, i: usize) -> (usize, usize) {
+        (i / self.len_y, i % self.len_y)
+    }
+}
+
 /// Part1
 fn stabilized_occupied_seats(layout: Vec<String>) -> usize {
     let seats = SeatLayout::new(layout);
-    let adj_getter = |lay: &SeatLayout, x, y| lay.get_adjacent(x, y);
-    let stab_seats = run_seat_rules_until_stable(&seats, 4, adj_getter);
+    let rules = SeatRules::adjacent();
+    let stab_seats = run_seat_rules_until_stable(&seats, &rules).layout;
 
-    stab_seats.count_total_occupied()
+    stab_seats.count_cells(rules.alphabet.occupied)
 }
 
-fn run_seat_rules_until_stable<F>(
+/// Steps generations until the layout stops changing or starts repeating.
+/// Every layout seen is remembered by hash, and a repeat is only reported
+/// once stepping another period from it gets back to the same layout.
+fn run_seat_rules_until_stable(layout: &SeatLayout, rules: &SeatRules) -> Simulation {
+    run_seat_rules_with(layout, rules, 1, |_, _| {})
+}
+
+/// Same as `run_seat_rules_until_stable`, splitting each generation into
+/// row bands across `threads` threads. Gives the same result for any count.
+fn run_seat_rules_in_parallel(
     layout: &SeatLayout,
-    occ_seat_limit: usize,
-    adj_getter: F,
-) -> SeatLayout
+    rules: &SeatRules,
+    threads: usize,
+) -> Simulation {
+    run_seat_rules_with(layout, rules, threads, |_, _| {})
+}
+
+/// Same as `run_seat_rules_until_stable`, calling `on_generation` with each
+/// generation's layout, starting from generation 0. Two layouts are swapped
+/// back and forth, so stepping doesn't allocate a new layout every generation.
+fn run_seat_rules_with<F>(
+    layout: &SeatLayout,
+    rules: &SeatRules,
+    threads: usize,
+    mut on_generation: F,
+) -> Simulation
 where
-    F: Fn(&SeatLayout, usize, usize) -> Vec<Option<char>>,
+    F: FnMut(usize, &SeatLayout),
 {
-    let mut new_layout = layout.clone();
+    let graph = VisibilityGraph::new(layout, rules);
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    let mut current = layout.clone();
+    let mut next = layout.clone();
+    let mut generation = 0;
+    on_generation(generation, &current);
+
+    loop {
+        current.step_into(&mut next, &graph, rules, threads);
+        if next == current {
+            return Simulation {
+                layout: current,
+                end: SimulationEnd::FixedPoint { generation },
+            };
+        }
 
-    for x in 0..layout.len_x() {
-        for y in 0..layout.len_y() {
-            let c = layout.get(x, y);
-            let adj = adj_getter(&layout, x, y);
-            let occ_count = count_adjacent_occupied(adj);
+        seen.insert(current.state_hash(), generation);
+        mem::swap(&mut current, &mut next);
+        generation += 1;
+        on_generation(generation, &current);
 
-            if c == 'L' && occ_count == 0 {
-                new_layout.layout[x][y] = '#'
-            }
-            if c == '#' && occ_count >= occ_seat_limit {
-                new_layout.layout[x][y] = 'L'
+        if let Some(&start) = seen.get(&current.state_hash()) {
+            let period = generation - start;
+            let after_period = (0..period).fold(current.clone(), |lay, _| lay.step(&graph, rules));
+
+            if after_period == current {
+                return Simulation {
+                    layout: current,
+                    end: SimulationEnd::Cycle { start, period },
+                };
             }
         }
     }
-
-    if new_layout.count_total_occupied() == layout.count_total_occupied() {
-        new_layout
-    } else {
-        run_seat_rules_until_stable(&new_layout, occ_seat_limit, adj_getter)
-    }
-}
-
-fn count_adjacent_occupied(adjacent: Vec<Option<char>>) -> usize {
-    adjacent
-        .into_iter()
-        .filter(|s| s.is_some())
-        .filter(|s| s.unwrap() == '#')
-        .count()
 }
 
 /// Part2
 fn stabilized_occupied_visible_seats(layout: Vec<String>) -> usize {
     let seats = SeatLayout::new(layout);
-    let adj_getter = |lay: &SeatLayout, x, y| lay.get_adjacent_vis(x, y);
-    let stab_seats = run_seat_rules_until_stable(&seats, 5, adj_getter);
+    let rules = SeatRules::line_of_sight();
+    let stab_seats = run_seat_rules_until_stable(&seats, &rules).layout;
 
-    stab_seats.count_total_occupied()
+    stab_seats.count_cells(rules.alphabet.occupied)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum FrameFormat {
+    /// The layout's characters, one line per row
+    Text,
+    /// A binary PPM image, drawing each cell as a `scale` by `scale` square
+    Ppm { scale: usize },
+}
+
+/// Clears the terminal and redraws each generation, waiting `delay` between
+/// frames. Cells that changed since the last frame are highlighted.
+fn play_in_terminal(layout: &SeatLayout, rules: &SeatRules, delay: Duration) -> Simulation {
+    let mut prev: Option<SeatLayout> = None;
+
+    run_seat_rules_with(layout, rules, 1, |generation, current| {
+        print!("\x1b[2J\x1b[H");
+        println!("{}{}", "Generation: ".green().bold(), generation);
+        println!("{}", render_colored_frame(prev.as_ref(), current, rules));
+        prev = Some(current.clone());
+        thread::sleep(delay);
+    })
+}
+
+fn render_colored_frame(
+    prev: Option<&SeatLayout>,
+    current: &SeatLayout,
+    rules: &SeatRules,
+) -> String {
+    (0..current.len_x())
+        .map(|x| {
+            (0..current.len_y())
+                .map(|y| {
+                    let c = current.get(x, y);
+                    let cell = if c == rules.alphabet.occupied {
+                        c.to_string().red()
+                    } else if c == rules.alphabet.empty {
+                        c.to_string().green()
+                    } else {
+                        c.to_string().dimmed()
+                    };
+
+                    match prev {
+                        Some(prev) if prev.get(x, y) != c => cell.bold().on_yellow().to_string(),
+                        _ => cell.to_string(),
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Writes every generation to `dir` as `frame_0000.txt` or `frame_0000.ppm`
+/// and so on. Returns the number of frames written.
+fn export_frames(
+    layout: &SeatLayout,
+    rules: &SeatRules,
+    dir: &Path,
+    format: FrameFormat,
+) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let mut written = 0;
+    let mut result = Ok(());
+
+    run_seat_rules_with(layout, rules, 1, |generation, current| {
+        if result.is_err() {
+            return;
+        }
+
+        let (extension, contents) = match format {
+            FrameFormat::Text => ("txt", current.to_text().into_bytes()),
+            FrameFormat::Ppm { scale } => ("ppm", current.to_ppm(rules, scale)),
+        };
+        let path = dir.join(format!("frame_{:04}.{}", generation, extension));
+        result = fs::write(path, contents);
+        written += 1;
+    });
+
+    result.map(|_| written)
+}
+
+/// Sorts neighbor positions and removes repeats and `own` position, since
+/// toroidal edges can wrap several offsets onto the same cell
+fn dedup_neighbors(positions: &mut Vec<(usize, usize)>, own: (usize, usize)) {
+    positions.sort_unstable();
+    positions.dedup();
+    positions.retain(|&position| position != own);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::input_utils::load_as_vec_string;
-    use colored::Colorize;
 
     static EXAMPLE_LAYOUT: &str = "L.LL.LL.LL
 LLLLLLL.LL
@@ -164,6 +543,268 @@ L.LLLLL.LL";
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn stabilized_occupied_seats_custom_alphabet() {
+        let expected = 37;
+        let layout = EXAMPLE_LAYOUT
+            .lines()
+            .map(|l| l.replace('L', "o").replace('.', " "))
+            .collect();
+        let rules = SeatRules {
+            alphabet: CellAlphabet {
+                empty: 'o',
+                occupied: 'x',
+                floor: ' ',
+            },
+            ..SeatRules::adjacent()
+        };
+        let actual = run_seat_rules_until_stable(&SeatLayout::new(layout), &rules)
+            .layout
+            .count_cells('x');
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn run_seat_rules_fixed_point_example() {
+        let layout = EXAMPLE_LAYOUT.lines().map(|l| l.to_string()).collect();
+        let seats = SeatLayout::new(layout);
+
+        let adjacent = run_seat_rules_until_stable(&seats, &SeatRules::adjacent());
+        let visible = run_seat_rules_until_stable(&seats, &SeatRules::line_of_sight());
+
+        assert_eq!(adjacent.end, SimulationEnd::FixedPoint { generation: 5 });
+        assert_eq!(visible.end, SimulationEnd::FixedPoint { generation: 6 });
+    }
+
+    #[test]
+    fn run_seat_rules_cycle() {
+        let seats = SeatLayout::new(vec!["LLL".to_string(); 3]);
+        let rules = SeatRules {
+            edges: EdgePolicy::Toroidal,
+            ..SeatRules::adjacent()
+        };
+        let actual = run_seat_rules_until_stable(&seats, &rules);
+
+        assert_eq!(
+            actual.end,
+            SimulationEnd::Cycle {
+                start: 0,
+                period: 2
+            }
+        );
+        assert_eq!(actual.layout, seats);
+    }
+
+    #[test]
+    fn run_seat_rules_cycle_after_lead_in() {
+        let seats = SeatLayout::new(vec!["L#".to_string()]);
+        let rules = SeatRules {
+            birth_limit: 1,
+            death_limit: 1,
+            ..SeatRules::adjacent()
+        };
+        let actual = run_seat_rules_until_stable(&seats, &rules);
+
+        assert_eq!(
+            actual.end,
+            SimulationEnd::Cycle {
+                start: 1,
+                period: 2
+            }
+        );
+        assert_eq!(actual.layout, SeatLayout::new(vec!["##".to_string()]));
+    }
+
+    #[test]
+    fn run_seat_rules_with_every_generation() {
+        let layout = EXAMPLE_LAYOUT.lines().map(|l| l.to_string()).collect();
+        let mut generations = vec![];
+        run_seat_rules_with(
+            &SeatLayout::new(layout),
+            &SeatRules::adjacent(),
+            1,
+            |gen, lay| generations.push((gen, lay.count_cells('#'))),
+        );
+
+        assert_eq!(
+            generations,
+            vec![(0, 0), (1, 71), (2, 20), (3, 51), (4, 30), (5, 37)]
+        );
+    }
+
+    #[test]
+    fn render_colored_frame_highlights_changes() {
+        colored::control::set_override(true);
+        let rules = SeatRules::adjacent();
+        let prev = SeatLayout::new(vec!["L.L".to_string()]);
+        let current = SeatLayout::new(vec!["#.L".to_string()]);
+
+        let expected = format!(
+            "{}{}{}",
+            "#".red().bold().on_yellow(),
+            ".".dimmed(),
+            "L".green()
+        );
+        let unchanged = format!("{}{}{}", "L".green(), ".".dimmed(), "L".green());
+
+        assert_eq!(
+            render_colored_frame(Some(&prev), &current, &rules),
+            expected
+        );
+        assert_eq!(render_colored_frame(None, &prev, &rules), unchanged);
+        assert_eq!(render_colored_frame(Some(&prev), &prev, &rules), unchanged);
+        assert_ne!(expected, "#.L");
+        colored::control::unset_override();
+    }
+
+    #[test]
+    fn play_in_terminal_test() {
+        let seats = SeatLayout::new(vec!["L.L".to_string(), "LLL".to_string()]);
+        let actual = play_in_terminal(&seats, &SeatRules::adjacent(), Duration::from_millis(0));
+
+        assert_eq!(actual.end, SimulationEnd::FixedPoint { generation: 2 });
+    }
+
+    #[test]
+    fn export_frames_test() {
+        let dir = std::env::temp_dir().join("seating_system_export_frames_test");
+        let _ = fs::remove_dir_all(&dir);
+        let seats = SeatLayout::new(vec!["L.L".to_string(), "LLL".to_string()]);
+        let rules = SeatRules::adjacent();
+
+        let text_frames = export_frames(&seats, &rules, &dir, FrameFormat::Text).unwrap();
+        let ppm_frames =
+            export_frames(&seats, &rules, &dir, FrameFormat::Ppm { scale: 2 }).unwrap();
+
+        assert_eq!(text_frames, 3);
+        assert_eq!(ppm_frames, 3);
+        assert_eq!(
+            fs::read_to_string(dir.join("frame_0001.txt")).unwrap(),
+            "#.#\n###\n"
+        );
+        let ppm = fs::read(dir.join("frame_0002.ppm")).unwrap();
+        let header = b"P6\n6 4\n255\n";
+        assert_eq!(&ppm[..header.len()], &header[..]);
+        assert_eq!(ppm.len(), header.len() + 6 * 4 * 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A pseudo-random layout, roughly a third each of floor, empty and occupied
+    fn generated_layout(len_x: usize, len_y: usize) -> SeatLayout {
+        let mut seed: u64 = 0x2020;
+        let layout = (0..len_x)
+            .map(|_| {
+                (0..len_y)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        ['.', 'L', '#'][(seed >> 33) as usize % 3]
+                    })
+                    .collect()
+            })
+            .collect();
+
+        SeatLayout { layout }
+    }
+
+    #[test]
+    fn run_seat_rules_in_parallel_matches_sequential() {
+        let seats = generated_layout(97, 61);
+        let toroidal = SeatRules {
+            edges: EdgePolicy::Toroidal,
+            birth_limit: 2,
+            ..SeatRules::adjacent()
+        };
+
+        for rules in &[SeatRules::adjacent(), SeatRules::line_of_sight(), toroidal] {
+            let expected = run_seat_rules_until_stable(&seats, rules);
+
+            for &threads in &[2, 3, 8, 200] {
+                let actual = run_seat_rules_in_parallel(&seats, rules, threads);
+
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn neighbor_positions_test() {
+        let layout = SeatLayout::new(vec![
+            "L.L".to_string(),
+            "...".to_string(),
+            "L.L".to_string(),
+        ]);
+        let count = |neighborhood, edges| {
+            let rules = SeatRules {
+                neighborhood,
+                edges,
+                ..SeatRules::adjacent()
+            };
+            layout.neighbor_positions(0, 0, &rules).len()
+        };
+
+        assert_eq!(count(Neighborhood::Adjacent, EdgePolicy::Bounded), 3);
+        assert_eq!(count(Neighborhood::Adjacent, EdgePolicy::Toroidal), 8);
+        assert_eq!(count(Neighborhood::VonNeumann, EdgePolicy::Bounded), 2);
+        assert_eq!(count(Neighborhood::VonNeumann, EdgePolicy::Toroidal), 4);
+        assert_eq!(count(Neighborhood::Radius(2), EdgePolicy::Bounded), 8);
+        assert_eq!(count(Neighborhood::Radius(2), EdgePolicy::Toroidal), 8);
+        assert_eq!(count(Neighborhood::LineOfSight, EdgePolicy::Bounded), 3);
+        assert_eq!(count(Neighborhood::LineOfSight, EdgePolicy::Toroidal), 3);
+    }
+
+    #[test]
+    fn visibility_graph_test() {
+        let layout = SeatLayout::new(vec![
+            "L.L".to_string(),
+            "...".to_string(),
+            "L.#".to_string(),
+        ]);
+        let graph = VisibilityGraph::new(&layout, &SeatRules::line_of_sight());
+
+        assert_eq!(graph.neighbors(0, 0), &[2, 6, 8]);
+        assert_eq!(graph.neighbors(2, 2), &[0, 2, 6]);
+        assert_eq!(graph.neighbors(1, 1), &[]);
+        assert_eq!(graph.position(8), (2, 2));
+    }
+
+    #[test]
+    fn line_of_sight_toroidal() {
+        let layout = SeatLayout::new(vec!["L..L.".to_string(), ".....".to_string()]);
+        let rules = SeatRules {
+            edges: EdgePolicy::Toroidal,
+            ..SeatRules::line_of_sight()
+        };
+        let actual = layout.neighbor_positions(0, 0, &rules);
+
+        assert_eq!(actual, vec![(0, 3)]);
+
+        // The diagonal repeats every lcm(2, 5) = 10 steps and hits (0, 1) at step 6
+        let layout = SeatLayout::new(vec!["LL...".to_string(), ".....".to_string()]);
+
+        assert_eq!(layout.first_seat(0, 0, (1, 1), &rules), Some((0, 1)));
+    }
+
+    #[test]
+    fn neighbor_positions_toroidal_thin_layouts() {
+        let rules = SeatRules {
+            edges: EdgePolicy::Toroidal,
+            ..SeatRules::adjacent()
+        };
+        let one_row = SeatLayout::new(vec!["LLL".to_string()]);
+        let two_rows = SeatLayout::new(vec!["LLL".to_string(), "LLL".to_string()]);
+
+        assert_eq!(
+            one_row.neighbor_positions(0, 0, &rules),
+            vec![(0, 1), (0, 2)]
+        );
+        assert_eq!(
+            two_rows.neighbor_positions(0, 0, &rules),
+            vec![(0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]
+        );
+    }
+
     #[test]
     fn stabilized_occupied_seats_from_input() {
         let expected = 2324;
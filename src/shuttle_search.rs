@@ -1,4 +1,9 @@
-#[derive(PartialEq, Clone)]
+use crate::ntheory::{self, CrtError};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::convert::TryFrom;
+
+#[derive(PartialEq, Debug, Clone)]
 enum BusId {
     Id(u64),
     NoConstraintsId,
@@ -6,86 +11,212 @@ enum BusId {
 
 /// Part1
 fn multiply_earliest_bus(schedule: String) -> u64 {
-    let (depart_time, parsed_sch) = parse_schedule(schedule);
-    let just_constrained_busses: Vec<u64> = parsed_sch
-        .into_iter()
-        .filter_map(|b| match b {
-            BusId::NoConstraintsId => None,
-            BusId::Id(id) => Some(id),
-        })
-        .collect();
+    let timetable = Timetable::from_schedule(&schedule);
+    let depart_time = timetable.earliest;
 
-    let (time, bus) = just_constrained_busses.into_iter().fold(
-        (u64::MAX, 0 as u64),
-        |(best_time, best_bus), bus| {
-            let time_till_next = bus - (depart_time as u64 % bus);
+    let (time, bus) =
+        timetable
+            .bus_ids()
+            .fold((u64::MAX, 0 as u64), |(best_time, best_bus), bus| {
+                let time_till_next = bus - (depart_time % bus);
 
-            if time_till_next < best_time {
-                (time_till_next, bus)
-            } else {
-                (best_time, best_bus)
-            }
-        },
-    );
+                if time_till_next < best_time {
+                    (time_till_next, bus)
+                } else {
+                    (best_time, best_bus)
+                }
+            });
 
     time * bus
 }
 
-fn find_subsequent_time(schedule: String) -> u64 {
-    let (_, parsed_sch) = parse_schedule(schedule);
-    let max_bus = parsed_sch
+#[derive(PartialEq, Debug)]
+enum ScheduleError {
+    /// This bus can never depart at its offset alongside the buses before it,
+    /// which can happen when bus ids share a factor
+    NoSolution { bus: u64, offset: u64 },
+    /// The earliest aligned time is later than `u64::MAX` minutes, or the
+    /// period it repeats with is larger than `u128::MAX`
+    Overflow,
+    /// The bus isn't in the timetable
+    UnknownBus(u64),
+}
+
+/// Part2
+/// Finds the earliest time where every bus departs at its index in the
+/// schedule, in milliseconds. Large schedules can land past `u64::MAX`
+/// milliseconds, so the result is a `u128`.
+fn find_subsequent_time(schedule: String) -> Result<u128, ScheduleError> {
+    let timetable = Timetable::from_schedule(&schedule);
+    let constraints: Vec<(u64, u64)> = timetable
+        .buses
         .iter()
-        .map(|bus| match bus {
-            BusId::Id(id) => id,
-            BusId::NoConstraintsId => &0,
-        })
         .enumerate()
-        .max_by(|x, y| x.1.cmp(y.1))
-        .unwrap();
-    let mut time = 0;
-
-    for n in 1..(u64::MAX / *max_bus.1) {
-        time = (max_bus.1 * n) - max_bus.0 as u64;
-        if time % 1_000_000 == 0 {
-            println!("Checking: {}", time);
-        }
+        .filter_map(|(offset, bus)| match bus {
+            BusId::Id(id) => Some((*id, offset as u64)),
+            BusId::NoConstraintsId => None,
+        })
+        .collect();
+
+    let minutes = timetable.first_aligned(&constraints)?;
+
+    Ok(u128::from(minutes) * MILLIS_PER_MINUTE)
+}
+
+const MILLIS_PER_MINUTE: u128 = 60_000;
+
+#[derive(PartialEq, Debug, Clone)]
+struct Timetable {
+    /// The earliest time you could leave, from the first line of the notes
+    earliest: u64,
+    buses: Vec<BusId>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+struct Departure {
+    time: u64,
+    bus: u64,
+}
 
-        let x = parsed_sch.iter().try_fold(time, |sub_time, bus| match bus {
-            BusId::Id(id) => {
-                if sub_time % id == 0 {
-                    Ok(sub_time + 1)
+impl Timetable {
+    fn from_schedule(schedule: &str) -> Timetable {
+        let schedule_lines: Vec<&str> = schedule.lines().collect();
+        let time = schedule_lines[0];
+        let busses = schedule_lines[1];
+
+        let buses = busses
+            .split(',')
+            .map(|b| {
+                if b == "x" {
+                    BusId::NoConstraintsId
                 } else {
-                    Err(())
+                    BusId::Id(b.parse().unwrap())
                 }
+            })
+            .collect();
+
+        Timetable {
+            earliest: time.parse().unwrap(),
+            buses,
+        }
+    }
+
+    /// Ids of the buses in service, in schedule order. A bus with id 0 would
+    /// loop in no time at all, so it's treated as out of service.
+    fn bus_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.buses.iter().filter_map(|b| match b {
+            BusId::Id(id) if *id > 0 => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// The next `count` departures at or after `after`, across every bus,
+    /// ordered by time and then bus id. Merges each bus's departures through
+    /// a heap, so it only visits the departures it returns.
+    fn next_departures(&self, after: u64, count: usize) -> Vec<Departure> {
+        let mut ids: Vec<u64> = self.bus_ids().collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut upcoming: BinaryHeap<Reverse<Departure>> = ids
+            .into_iter()
+            .filter_map(|bus| {
+                let time = after.checked_add(bus - 1)? / bus * bus;
+                Some(Reverse(Departure { time, bus }))
+            })
+            .collect();
+
+        let mut departures = Vec::with_capacity(count);
+        while departures.len() < count {
+            let Reverse(departure) = match upcoming.pop() {
+                Some(next) => next,
+                None => break,
+            };
+            departures.push(departure);
+
+            if let Some(time) = departure.time.checked_add(departure.bus) {
+                upcoming.push(Reverse(Departure {
+                    time,
+                    bus: departure.bus,
+                }));
             }
-            BusId::NoConstraintsId => Ok(sub_time + 1),
-        });
+        }
+
+        departures
+    }
 
-        if x.is_ok() {
-            break;
+    /// Every time from `start` to `end`, inclusive, that `bus` departs at, or
+    /// `None` if the bus isn't in the timetable
+    fn departures_of(&self, bus: u64, start: u64, end: u64) -> Option<Vec<u64>> {
+        if !self.bus_ids().any(|id| id == bus) {
+            return None;
         }
+
+        let first = match start.checked_add(bus - 1) {
+            Some(rounded) => rounded / bus * bus,
+            None => return Some(vec![]),
+        };
+
+        Some((first..=end).step_by(bus as usize).collect())
     }
 
-    time
-}
+    /// The earliest time where each `(bus, offset)` bus departs `offset`
+    /// minutes later. Solved with the Chinese Remainder Theorem, so bus ids
+    /// don't need to be coprime.
+    fn first_aligned(&self, constraints: &[(u64, u64)]) -> Result<u64, ScheduleError> {
+        if let Some(&(bus, _)) = constraints
+            .iter()
+            .find(|(bus, _)| !self.bus_ids().any(|id| id == *bus))
+        {
+            return Err(ScheduleError::UnknownBus(bus));
+        }
+
+        let congruences: Vec<(u128, u128)> = constraints
+            .iter()
+            .map(|&(bus, offset)| {
+                let bus = bus as u128;
+                ((bus - offset as u128 % bus) % bus, bus)
+            })
+            .collect();
+
+        let (time, _) = ntheory::crt(&congruences).map_err(|err| match err {
+            CrtError::NoSolution(i) => ScheduleError::NoSolution {
+                bus: constraints[i].0,
+                offset: constraints[i].1,
+            },
+            CrtError::Overflow => ScheduleError::Overflow,
+            CrtError::ZeroModulus(i) => ScheduleError::UnknownBus(constraints[i].0),
+        })?;
+
+        u64::try_from(time).map_err(|_| ScheduleError::Overflow)
+    }
+
+    /// A table with a row for each minute from `start` to `end`, marking
+    /// the buses that depart with `D`, like the one in the puzzle text
+    fn board(&self, start: u64, end: u64) -> String {
+        let headers: Vec<String> = self.bus_ids().map(|id| format!("bus {}", id)).collect();
+        let time_width = end.to_string().len().max("time".len());
+
+        let mut board = format!("{:<width$}", "time", width = time_width);
+        for header in &headers {
+            board.push_str("   ");
+            board.push_str(header);
+        }
+        board = board.trim_end().to_string();
+        board.push('\n');
 
-fn parse_schedule(schedule: String) -> (usize, Vec<BusId>) {
-    let schedule_lines: Vec<&str> = schedule.lines().collect();
-    let time = schedule_lines[0];
-    let busses = schedule_lines[1];
-
-    let bus_vec = busses
-        .split(',')
-        .map(|b| {
-            if b == "x" {
-                BusId::NoConstraintsId
-            } else {
-                BusId::Id(b.parse().unwrap())
+        for time in start..=end {
+            let mut row = format!("{:<width$}", time, width = time_width);
+            for (bus, header) in self.bus_ids().zip(&headers) {
+                let marker = if time.is_multiple_of(bus) { 'D' } else { '.' };
+                row.push_str(&format!("   {:^width$}", marker, width = header.len()));
             }
-        })
-        .collect();
+            board.push_str(row.trim_end());
+            board.push('\n');
+        }
 
-    (time.parse().unwrap(), bus_vec)
+        board
+    }
 }
 
 #[cfg(test)]
@@ -123,7 +254,7 @@ mod tests {
 
     #[test]
     fn find_subsequent_time_example() {
-        let expected = 1068781;
+        let expected = Ok(1068781 * 60_000);
         let actual = find_subsequent_time(EXAMPLE_SCHEDULE.to_string());
 
         assert_eq!(actual, expected);
@@ -140,21 +271,112 @@ mod tests {
         ];
 
         for ex in examples {
-            let actual = find_subsequent_time(ex.0.to_string());
+            let actual = find_subsequent_time(ex.0.to_string()).unwrap();
 
-            assert_eq!(actual, ex.1);
+            assert_eq!(actual, ex.1 * 60_000);
             println!("Passed for {}", actual);
         }
     }
 
-    // Part2 - This probably works, but is absurdly slow, taking a day or
-    // longer to reach answer. So we don't want this running on `cargo test`.
-    // #[test]
+    #[test]
+    fn find_subsequent_time_shared_factors() {
+        assert_eq!(find_subsequent_time("0\n4,x,6".to_string()), Ok(240_000));
+        assert_eq!(find_subsequent_time("0\n6,x,x,9".to_string()), Ok(360_000));
+        assert_eq!(
+            find_subsequent_time("0\n4,6".to_string()),
+            Err(ScheduleError::NoSolution { bus: 6, offset: 1 })
+        );
+    }
+
+    #[test]
+    fn find_subsequent_time_overflow() {
+        let schedule = "0\n1000000007,1000000009,998244353".to_string();
+
+        assert_eq!(find_subsequent_time(schedule), Err(ScheduleError::Overflow));
+    }
+
+    #[test]
+    fn next_departures_example() {
+        let timetable = Timetable::from_schedule(EXAMPLE_SCHEDULE);
+        let expected = vec![
+            Departure { time: 944, bus: 59 },
+            Departure { time: 945, bus: 7 },
+            Departure { time: 949, bus: 13 },
+            Departure { time: 950, bus: 19 },
+            Departure { time: 952, bus: 7 },
+        ];
+
+        assert_eq!(timetable.next_departures(939, 5), expected);
+        assert_eq!(
+            timetable.next_departures(945, 1),
+            vec![Departure { time: 945, bus: 7 }]
+        );
+    }
+
+    #[test]
+    fn departures_of_window() {
+        let timetable = Timetable::from_schedule(EXAMPLE_SCHEDULE);
+
+        assert_eq!(
+            timetable.departures_of(13, 930, 970),
+            Some(vec![936, 949, 962])
+        );
+        assert_eq!(timetable.departures_of(7, 945, 952), Some(vec![945, 952]));
+        assert_eq!(timetable.departures_of(8, 0, 100), None);
+    }
+
+    #[test]
+    fn first_aligned_subset() {
+        let timetable = Timetable::from_schedule(EXAMPLE_SCHEDULE);
+
+        assert_eq!(timetable.first_aligned(&[(7, 0), (13, 1)]), Ok(77));
+        assert_eq!(timetable.first_aligned(&[]), Ok(0));
+        assert_eq!(
+            timetable.first_aligned(&[(7, 0), (11, 1)]),
+            Err(ScheduleError::UnknownBus(11))
+        );
+    }
+
+    #[test]
+    fn timetable_skips_zero_ids() {
+        let timetable = Timetable::from_schedule("10\n0,x,3");
+
+        assert_eq!(timetable.bus_ids().collect::<Vec<u64>>(), vec![3]);
+        assert_eq!(
+            timetable.next_departures(10, 2),
+            vec![
+                Departure { time: 12, bus: 3 },
+                Departure { time: 15, bus: 3 }
+            ]
+        );
+        assert_eq!(timetable.departures_of(0, 0, 10), None);
+        assert_eq!(
+            timetable.first_aligned(&[(0, 0)]),
+            Err(ScheduleError::UnknownBus(0))
+        );
+        assert_eq!(multiply_earliest_bus("10\n0,x,3".to_string()), 6);
+    }
+
+    #[test]
+    fn board_example() {
+        let timetable = Timetable::from_schedule(EXAMPLE_SCHEDULE);
+        let expected = "\
+time   bus 7   bus 13   bus 59   bus 31   bus 19
+943      .       .        .        .        .
+944      .       .        D        .        .
+945      D       .        .        .        .
+";
+
+        assert_eq!(timetable.board(943, 945), expected);
+    }
+
+    // Part2
+    #[test]
     fn find_subsequent_time_from_input() {
-        let expected = 939490236001473;
+        let expected = 939490236001473 * 60_000;
 
         let schedule = load_as_string("day13");
-        let actual = find_subsequent_time(schedule);
+        let actual = find_subsequent_time(schedule).unwrap();
         println!(
             "{}{}",
             "Earliest time where busses arrive at each subsequent minute: "
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use crate::interval_set::IntervalSet;
 
 #[derive(PartialEq, Debug, Clone)]
 struct Tickets {
@@ -7,14 +7,11 @@ struct Tickets {
     nearby_tickets: Vec<Vec<usize>>,
 }
 
-// A Vec of (low, high) ranges
-type Ranges = Vec<(usize, usize)>;
-
 #[derive(PartialEq, Debug, Clone)]
 struct TicketRule {
     name: String,
     column: Option<u8>,
-    ranges: Ranges,
+    ranges: IntervalSet,
 }
 
 /// Part1
@@ -27,91 +24,340 @@ fn ticket_error_rate(raw_tickets: String) -> usize {
         .nearby_tickets
         .iter()
         .flatten()
-        .filter(|field| {
-            !combined_ranges
-                .iter()
-                .any(|range| **field >= range.0 && **field <= range.1)
-        })
-        .map(|f| f.clone())
+        .filter(|field| !combined_ranges.contains(**field))
+        .copied()
         .collect();
 
     invalid_numbers.iter().sum()
 }
 
-fn combine_ranges(tickets: &Tickets) -> Ranges {
-    let mut all_ranges: Vec<&(usize, usize)> = tickets
+fn combine_ranges(tickets: &Tickets) -> IntervalSet {
+    tickets
         .rules
         .iter()
-        .map(|rule| &rule.ranges)
-        .flatten()
-        .collect();
-    all_ranges.sort();
+        .fold(IntervalSet::new(), |combined, rule| {
+            combined.union(&rule.ranges)
+        })
+}
 
-    let first_range = vec![all_ranges[0].clone()];
-    all_ranges
-        .into_iter()
-        .skip(1)
-        .fold(first_range, |mut ranges, range| {
-            let prev_range = ranges.pop().unwrap();
-            if prev_range.1 + 1 >= range.0 {
-                ranges.push((prev_range.0, range.1));
-            } else {
-                ranges.push(prev_range);
-                ranges.push(range.clone());
-            }
+#[derive(PartialEq, Debug)]
+enum ColumnAssignment {
+    /// Exactly one way to assign rules, indexed by column and holding the rule index
+    Unique(Vec<usize>),
+    /// Every valid assignment found, up to the requested limit but always at
+    /// least two
+    Ambiguous(Vec<Vec<usize>>),
+}
 
-            ranges
+#[derive(PartialEq, Debug)]
+enum ColumnError {
+    /// Columns that can't all be given a rule, sorted by column index
+    Unsatisfiable(Vec<usize>),
+    /// More than one assignment fits, holding the first two found
+    Ambiguous(Vec<Vec<usize>>),
+}
+
+/// Returns a new Tickets, removing invalid tickets and mapping the rule.column fields.
+/// Rules are sorted by their assigned column.
+fn map_columns(tickets: &Tickets) -> Result<Tickets, ColumnError> {
+    let mut nearby_tickets = filter_valid_tickets(tickets);
+    // Drop your ticket, which `filter_valid_tickets` appends last
+    nearby_tickets.pop();
+
+    let assignment = match assign_columns(tickets, 2)? {
+        ColumnAssignment::Unique(assignment) => assignment,
+        ColumnAssignment::Ambiguous(found) => return Err(ColumnError::Ambiguous(found)),
+    };
+
+    let mapped_rules = assignment
+        .into_iter()
+        .enumerate()
+        .map(|(column, rule_i)| TicketRule {
+            column: Some(column as u8),
+            ..tickets.rules[rule_i].clone()
         })
+        .collect();
+
+    Ok(Tickets {
+        rules: mapped_rules,
+        nearby_tickets,
+        ..tickets.clone()
+    })
 }
 
-/// Returns a new Tickets, removing invalid tickets and mapping the rule.column fields
-fn map_columns(tickets: &Tickets) -> Tickets {
-    let valid_tickets = filter_valid_tickets(&tickets);
-    let mut columns: Vec<(Vec<usize>, usize)> = vec![];
+/// Solves which rule belongs to each column as a bipartite matching between
+/// columns and rules. Returns the unique assignment, or up to `limit`
+/// assignments when more than one is valid. At least two are always
+/// returned in that case, so an ambiguous result can't pass for a unique one.
+fn assign_columns(tickets: &Tickets, limit: usize) -> Result<ColumnAssignment, ColumnError> {
+    let candidates = column_candidates(tickets);
 
-    for i in 0..tickets.your_ticket.len() {
-        columns.push((valid_tickets.iter().map(|tic| tic[i]).collect(), i));
+    let no_candidates: Vec<usize> = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, rules)| rules.is_empty())
+        .map(|(column, _)| column)
+        .collect();
+    if !no_candidates.is_empty() {
+        return Err(ColumnError::Unsatisfiable(no_candidates));
     }
 
-    let mut mapped_rules: Vec<TicketRule> = vec![];
-    let mut used_rules: HashSet<String> = HashSet::new();
+    let matching = max_matching(&candidates, tickets.rules.len());
+    if matching.iter().any(|rule| rule.is_none()) {
+        return Err(ColumnError::Unsatisfiable(hall_violators(
+            &candidates,
+            &matching,
+            tickets.rules.len(),
+        )));
+    }
 
-    while columns.len() > 0 {
-        let column = columns.pop().unwrap();
-        let matching_rules: Vec<&TicketRule> = tickets
-            .rules
-            .iter()
-            .filter(|rule| !used_rules.contains(&rule.name))
-            .filter(|rule| {
-                column.0.iter().all(|field| {
-                    rule.ranges
+    let matching = matching.into_iter().flatten().collect();
+    let mut found = vec![];
+    enumerate_assignments(
+        candidates,
+        matching,
+        tickets.rules.len(),
+        limit.max(2),
+        &mut found,
+    );
+    found.sort();
+    if found.len() == 1 {
+        Ok(ColumnAssignment::Unique(found.remove(0)))
+    } else {
+        found.truncate(limit.max(2));
+        Ok(ColumnAssignment::Ambiguous(found))
+    }
+}
+
+/// For each column, the indexes of the rules that every valid ticket's field fits
+fn column_candidates(tickets: &Tickets) -> Vec<Vec<usize>> {
+    let valid_tickets = filter_valid_tickets(tickets);
+
+    (0..tickets.your_ticket.len())
+        .map(|column| {
+            tickets
+                .rules
+                .iter()
+                .enumerate()
+                .filter(|(_, rule)| {
+                    valid_tickets
                         .iter()
-                        .any(|range| *field >= range.0 && *field <= range.1)
+                        .all(|tic| rule.ranges.contains(tic[column]))
                 })
-            })
-            .collect();
+                .map(|(rule_i, _)| rule_i)
+                .collect()
+        })
+        .collect()
+}
 
-        if matching_rules.len() == 1 {
-            let rule = matching_rules[0];
-            used_rules.insert(rule.name.clone());
-            mapped_rules.push(TicketRule {
-                column: Some(column.1 as u8),
-                ..rule.clone()
-            });
-        } else {
-            columns.insert(0, column);
+/// Kuhn's augmenting path matching. Returns the matched rule for each column.
+fn max_matching(candidates: &[Vec<usize>], rule_count: usize) -> Vec<Option<usize>> {
+    let mut rule_owner: Vec<Option<usize>> = vec![None; rule_count];
+
+    for column in 0..candidates.len() {
+        let mut seen = vec![false; rule_count];
+        try_augment(column, candidates, &mut seen, &mut rule_owner);
+    }
+
+    let mut matching = vec![None; candidates.len()];
+    for (rule_i, owner) in rule_owner.into_iter().enumerate() {
+        if let Some(column) = owner {
+            matching[column] = Some(rule_i);
         }
     }
+    matching
+}
 
-    let nearby_tickets = valid_tickets
-        .into_iter()
-        .filter(|tic| *tic != tickets.your_ticket)
+fn try_augment(
+    column: usize,
+    candidates: &[Vec<usize>],
+    seen: &mut [bool],
+    rule_owner: &mut [Option<usize>],
+) -> bool {
+    for &rule_i in &candidates[column] {
+        if seen[rule_i] {
+            continue;
+        }
+        seen[rule_i] = true;
+
+        let free = match rule_owner[rule_i] {
+            None => true,
+            Some(owner) => try_augment(owner, candidates, seen, rule_owner),
+        };
+        if free {
+            rule_owner[rule_i] = Some(column);
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Columns reachable by alternating paths from the unmatched columns. Together
+/// they fit fewer rules than there are columns, so no assignment can cover them.
+fn hall_violators(
+    candidates: &[Vec<usize>],
+    matching: &[Option<usize>],
+    rule_count: usize,
+) -> Vec<usize> {
+    let mut rule_owner: Vec<Option<usize>> = vec![None; rule_count];
+    for (column, rule) in matching.iter().enumerate() {
+        if let Some(rule_i) = rule {
+            rule_owner[*rule_i] = Some(column);
+        }
+    }
+
+    let mut reached = vec![false; candidates.len()];
+    let mut queue: Vec<usize> = (0..candidates.len())
+        .filter(|&column| matching[column].is_none())
         .collect();
-    Tickets {
-        rules: mapped_rules,
-        nearby_tickets: nearby_tickets,
-        ..tickets.clone()
+    for &column in &queue {
+        reached[column] = true;
+    }
+
+    while let Some(column) = queue.pop() {
+        for &rule_i in &candidates[column] {
+            if let Some(owner) = rule_owner[rule_i] {
+                if !reached[owner] {
+                    reached[owner] = true;
+                    queue.push(owner);
+                }
+            }
+        }
+    }
+
+    (0..candidates.len()).filter(|&c| reached[c]).collect()
+}
+
+/// Enumerates up to `limit` complete assignments, starting from `matching`.
+/// Each step looks for a second assignment that differs from the current
+/// one, then splits the search into the assignments that keep one of the
+/// differing pairs and the ones that don't, so every result costs only a
+/// polynomial amount of work.
+fn enumerate_assignments(
+    candidates: Vec<Vec<usize>>,
+    matching: Vec<usize>,
+    rule_count: usize,
+    limit: usize,
+    found: &mut Vec<Vec<usize>>,
+) {
+    if found.len() >= limit {
+        return;
+    }
+    let (column, alternative) = match alternative_assignment(&candidates, &matching, rule_count) {
+        Some(split) => split,
+        None => {
+            found.push(matching);
+            return;
+        }
+    };
+    let rule_i = matching[column];
+
+    let mut kept = candidates.clone();
+    for (other, rules) in kept.iter_mut().enumerate() {
+        if other == column {
+            *rules = vec![rule_i];
+        } else {
+            rules.retain(|&r| r != rule_i);
+        }
+    }
+    enumerate_assignments(kept, matching, rule_count, limit, found);
+
+    let mut dropped = candidates;
+    dropped[column].retain(|&r| r != rule_i);
+    enumerate_assignments(dropped, alternative, rule_count, limit, found);
+}
+
+/// Finds another assignment by moving a column onto an unused rule, or by
+/// rotating rules along an alternating cycle of the matching. Returns the
+/// first column whose rule changed, with the new assignment.
+fn alternative_assignment(
+    candidates: &[Vec<usize>],
+    matching: &[usize],
+    rule_count: usize,
+) -> Option<(usize, Vec<usize>)> {
+    let mut rule_owner: Vec<Option<usize>> = vec![None; rule_count];
+    for (column, &rule_i) in matching.iter().enumerate() {
+        rule_owner[rule_i] = Some(column);
+    }
+
+    for (column, rules) in candidates.iter().enumerate() {
+        if let Some(&free) = rules.iter().find(|&&r| rule_owner[r].is_none()) {
+            let mut alternative = matching.to_vec();
+            alternative[column] = free;
+            return Some((column, alternative));
+        }
+    }
+
+    let mut visited = vec![false; candidates.len()];
+    let mut on_stack = vec![false; candidates.len()];
+    let mut stack = vec![];
+    for column in 0..candidates.len() {
+        if visited[column] {
+            continue;
+        }
+        let cycle = find_cycle(
+            column,
+            candidates,
+            matching,
+            &rule_owner,
+            &mut visited,
+            &mut on_stack,
+            &mut stack,
+        );
+        if let Some(cycle) = cycle {
+            let mut alternative = matching.to_vec();
+            for (i, &column) in cycle.iter().enumerate() {
+                alternative[column] = matching[cycle[(i + 1) % cycle.len()]];
+            }
+            return Some((cycle[0], alternative));
+        }
+    }
+
+    None
+}
+
+/// Depth first search over columns, where a column points at the owner of
+/// each other rule it could take. A cycle means those columns can pass
+/// their rules along it.
+fn find_cycle(
+    column: usize,
+    candidates: &[Vec<usize>],
+    matching: &[usize],
+    rule_owner: &[Option<usize>],
+    visited: &mut [bool],
+    on_stack: &mut [bool],
+    stack: &mut Vec<usize>,
+) -> Option<Vec<usize>> {
+    visited[column] = true;
+    on_stack[column] = true;
+    stack.push(column);
+
+    for &rule_i in &candidates[column] {
+        if rule_i == matching[column] {
+            continue;
+        }
+        let next = match rule_owner[rule_i] {
+            Some(owner) => owner,
+            None => continue,
+        };
+        if on_stack[next] {
+            let start = stack.iter().position(|&c| c == next).unwrap();
+            return Some(stack[start..].to_vec());
+        }
+        if !visited[next] {
+            let cycle = find_cycle(
+                next, candidates, matching, rule_owner, visited, on_stack, stack,
+            );
+            if cycle.is_some() {
+                return cycle;
+            }
+        }
     }
+
+    stack.pop();
+    on_stack[column] = false;
+    None
 }
 
 fn filter_valid_tickets(tickets: &Tickets) -> Vec<Vec<usize>> {
@@ -120,13 +366,7 @@ fn filter_valid_tickets(tickets: &Tickets) -> Vec<Vec<usize>> {
     let mut valid_tickets: Vec<Vec<usize>> = tickets
         .nearby_tickets
         .iter()
-        .filter(|ticket| {
-            ticket.iter().all(|field| {
-                combined_ranges
-                    .iter()
-                    .any(|range| *field >= range.0 && *field <= range.1)
-            })
-        })
+        .filter(|ticket| ticket.iter().all(|field| combined_ranges.contains(*field)))
         .map(|tic| tic.clone())
         .collect();
 
@@ -134,28 +374,420 @@ fn filter_valid_tickets(tickets: &Tickets) -> Vec<Vec<usize>> {
     valid_tickets
 }
 
-/// Part2
-fn product_departure(raw_tickets: String) -> usize {
-    let tickets = parse_tickets(raw_tickets);
+#[derive(PartialEq, Debug)]
+struct TicketReport {
+    /// Index into `Tickets.nearby_tickets`
+    ticket: usize,
+    invalid_fields: Vec<InvalidField>,
+}
 
-    let tickets_with_columns = map_columns(&tickets);
+#[derive(PartialEq, Debug)]
+struct InvalidField {
+    column: usize,
+    value: usize,
+    /// Set when the value is within the tolerance of some rule's range
+    near_miss: Option<NearMiss>,
+}
+
+#[derive(PartialEq, Debug)]
+struct NearMiss {
+    rule: String,
+    range: (usize, usize),
+    off_by: usize,
+}
 
-    let departure_rules: Vec<TicketRule> = tickets_with_columns
+/// Lists every field value that fits no rule, for each nearby ticket. Values
+/// that miss a rule's range by at most `tolerance` name the closest range.
+fn validation_report(tickets: &Tickets, tolerance: usize) -> Vec<TicketReport> {
+    let combined_ranges = combine_ranges(tickets);
+
+    tickets
+        .nearby_tickets
+        .iter()
+        .enumerate()
+        .map(|(ticket, fields)| TicketReport {
+            ticket,
+            invalid_fields: fields
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| !combined_ranges.contains(**value))
+                .map(|(column, &value)| InvalidField {
+                    column,
+                    value,
+                    near_miss: closest_range(tickets, value)
+                        .filter(|miss| miss.off_by <= tolerance),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Finds the rule range nearest to `value`, preferring earlier rules on ties
+fn closest_range(tickets: &Tickets, value: usize) -> Option<NearMiss> {
+    tickets
         .rules
-        .into_iter()
-        .filter(|rule| rule.name.starts_with("departure"))
+        .iter()
+        .flat_map(|rule| rule.ranges.ranges().iter().map(move |range| (rule, range)))
+        .map(|(rule, &range)| {
+            let off_by = if value < range.0 {
+                range.0 - value
+            } else {
+                value.saturating_sub(range.1)
+            };
+
+            NearMiss {
+                rule: rule.name.clone(),
+                range,
+                off_by,
+            }
+        })
+        .min_by_key(|miss| miss.off_by)
+}
+
+/// Renders the invalid fields as an aligned text table, one row per field,
+/// followed by a count of valid tickets.
+fn render_report_table(report: &[TicketReport]) -> String {
+    let rows: Vec<[String; 6]> = report
+        .iter()
+        .flat_map(|tic| {
+            tic.invalid_fields.iter().map(move |field| {
+                let (rule, range, off_by) = match &field.near_miss {
+                    Some(miss) => (
+                        miss.rule.clone(),
+                        format!("{}-{}", miss.range.0, miss.range.1),
+                        miss.off_by.to_string(),
+                    ),
+                    None => ("-".to_string(), "-".to_string(), "-".to_string()),
+                };
+
+                [
+                    tic.ticket.to_string(),
+                    field.column.to_string(),
+                    field.value.to_string(),
+                    rule,
+                    range,
+                    off_by,
+                ]
+            })
+        })
+        .collect();
+
+    let header = [
+        "ticket",
+        "column",
+        "value",
+        "closest rule",
+        "range",
+        "off by",
+    ];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain(std::iter::once(header[i].len()))
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    let format_row = |cells: Vec<&str>| -> String {
+        cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![format_row(header.to_vec())];
+    for row in &rows {
+        lines.push(format_row(row.iter().map(|c| c.as_str()).collect()));
+    }
+
+    let valid_count = report
+        .iter()
+        .filter(|tic| tic.invalid_fields.is_empty())
+        .count();
+    lines.push(format!("{} of {} tickets valid", valid_count, report.len()));
+
+    lines.join("\n")
+}
+
+/// Renders the report as a JSON array with one object per nearby ticket
+fn render_report_json(report: &[TicketReport]) -> String {
+    let tickets: Vec<String> = report
+        .iter()
+        .map(|tic| {
+            let fields: Vec<String> = tic
+                .invalid_fields
+                .iter()
+                .map(|field| {
+                    let near_miss = match &field.near_miss {
+                        Some(miss) => format!(
+                            "{{\"rule\":\"{}\",\"low\":{},\"high\":{},\"off_by\":{}}}",
+                            escape_json(&miss.rule),
+                            miss.range.0,
+                            miss.range.1,
+                            miss.off_by
+                        ),
+                        None => "null".to_string(),
+                    };
+
+                    format!(
+                        "{{\"column\":{},\"value\":{},\"near_miss\":{}}}",
+                        field.column, field.value, near_miss
+                    )
+                })
+                .collect();
+
+            format!(
+                "{{\"ticket\":{},\"valid\":{},\"invalid_fields\":[{}]}}",
+                tic.ticket,
+                fields.is_empty(),
+                fields.join(",")
+            )
+        })
         .collect();
 
-    let departure_fields: Vec<usize> = departure_rules
+    format!("[{}]", tickets.join(","))
+}
+
+fn escape_json(raw: &str) -> String {
+    raw.chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            c if c.is_control() => format!("\\u{:04x}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct TranslatedTickets {
+    your_ticket: TicketRecord,
+    nearby_tickets: Vec<TicketRecord>,
+}
+
+/// A ticket's field values keyed by rule name, in the order the rules were listed
+#[derive(PartialEq, Debug, Clone)]
+struct TicketRecord {
+    fields: Vec<(String, usize)>,
+}
+
+impl TicketRecord {
+    fn get(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| *value)
+    }
+
+    /// Keeps only the fields whose name starts with `prefix`
+    fn with_prefix(&self, prefix: &str) -> TicketRecord {
+        TicketRecord {
+            fields: self
+                .fields
+                .iter()
+                .filter(|(field, _)| field.starts_with(prefix))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl TranslatedTickets {
+    fn with_prefix(&self, prefix: &str) -> TranslatedTickets {
+        TranslatedTickets {
+            your_ticket: self.your_ticket.with_prefix(prefix),
+            nearby_tickets: self
+                .nearby_tickets
+                .iter()
+                .map(|tic| tic.with_prefix(prefix))
+                .collect(),
+        }
+    }
+}
+
+/// Maps the columns, then names the fields of your ticket and every valid
+/// nearby ticket.
+fn translate_tickets(tickets: &Tickets) -> Result<TranslatedTickets, ColumnError> {
+    let mapped = map_columns(tickets)?;
+    let columns: Vec<(String, usize)> = tickets
+        .rules
         .iter()
-        .map(|rule| tickets.your_ticket[rule.column.unwrap() as usize])
+        .map(|rule| {
+            let mapped_rule = mapped.rules.iter().find(|r| r.name == rule.name).unwrap();
+            (rule.name.clone(), mapped_rule.column.unwrap() as usize)
+        })
         .collect();
 
-    departure_fields.iter().product()
+    let translate = |ticket: &Vec<usize>| TicketRecord {
+        fields: columns
+            .iter()
+            .map(|(name, column)| (name.clone(), ticket[*column]))
+            .collect(),
+    };
+
+    Ok(TranslatedTickets {
+        your_ticket: translate(&mapped.your_ticket),
+        nearby_tickets: mapped.nearby_tickets.iter().map(translate).collect(),
+    })
 }
 
+/// Renders a header row of field names, then your ticket, then each nearby ticket
+fn records_to_csv(translated: &TranslatedTickets) -> String {
+    let header: Vec<String> = translated
+        .your_ticket
+        .fields
+        .iter()
+        .map(|(name, _)| escape_csv(name))
+        .collect();
+
+    let mut lines = vec![header.join(",")];
+    for record in std::iter::once(&translated.your_ticket).chain(&translated.nearby_tickets) {
+        let values: Vec<String> = record
+            .fields
+            .iter()
+            .map(|(_, value)| value.to_string())
+            .collect();
+        lines.push(values.join(","));
+    }
+
+    lines.join("\n")
+}
+
+fn escape_csv(raw: &str) -> String {
+    if raw.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", raw.replace('"', "\"\""))
+    } else {
+        raw.to_string()
+    }
+}
+
+fn records_to_json(translated: &TranslatedTickets) -> String {
+    let record_json = |record: &TicketRecord| {
+        let fields: Vec<String> = record
+            .fields
+            .iter()
+            .map(|(name, value)| format!("\"{}\":{}", escape_json(name), value))
+            .collect();
+
+        format!("{{{}}}", fields.join(","))
+    };
+    let nearby: Vec<String> = translated.nearby_tickets.iter().map(record_json).collect();
+
+    format!(
+        "{{\"your_ticket\":{},\"nearby_tickets\":[{}]}}",
+        record_json(&translated.your_ticket),
+        nearby.join(",")
+    )
+}
+
+/// Part2
+fn product_departure(raw_tickets: String) -> usize {
+    let tickets = parse_tickets(raw_tickets);
+
+    let translated = translate_tickets(&tickets).unwrap();
+
+    translated
+        .your_ticket
+        .with_prefix("departure")
+        .fields
+        .iter()
+        .map(|(_, value)| value)
+        .product()
+}
+
+#[derive(PartialEq, Debug)]
+struct RuleInference {
+    /// One proposed rule per column, named `column N`
+    rules: Vec<TicketRule>,
+    overlaps: Vec<ColumnOverlap>,
+}
+
+#[derive(PartialEq, Debug)]
+struct ColumnOverlap {
+    columns: (usize, usize),
+    /// Share of the smaller rule's values that the other rule also covers
+    shared: f64,
+}
+
+/// Proposes range rules for each column from observed ticket values. Values
+/// within `max_gap` of each other are clustered into a single range. Pairs of
+/// columns sharing more than `overlap_limit` of their values are flagged.
+fn infer_rules(tickets: &Tickets, max_gap: usize, overlap_limit: f64) -> RuleInference {
+    let corpus: Vec<Vec<usize>> = if tickets.rules.is_empty() {
+        std::iter::once(&tickets.your_ticket)
+            .chain(&tickets.nearby_tickets)
+            .cloned()
+            .collect()
+    } else {
+        filter_valid_tickets(tickets)
+    };
+
+    let rules: Vec<TicketRule> = (0..tickets.your_ticket.len())
+        .map(|column| {
+            let values: Vec<usize> = corpus.iter().map(|tic| tic[column]).collect();
+
+            TicketRule {
+                name: format!("column {}", column),
+                column: Some(column as u8),
+                ranges: cluster_values(values, max_gap),
+            }
+        })
+        .collect();
+
+    let mut overlaps = vec![];
+    for (i, a) in rules.iter().enumerate() {
+        for (j, b) in rules.iter().enumerate().skip(i + 1) {
+            let smaller = a.ranges.len().min(b.ranges.len());
+            let shared = a.ranges.intersection(&b.ranges).len() as f64 / smaller as f64;
+
+            if shared > overlap_limit {
+                overlaps.push(ColumnOverlap {
+                    columns: (i, j),
+                    shared,
+                });
+            }
+        }
+    }
+
+    RuleInference { rules, overlaps }
+}
+
+fn cluster_values(mut values: Vec<usize>, max_gap: usize) -> IntervalSet {
+    values.sort_unstable();
+    values.dedup();
+
+    let mut clusters: Vec<(usize, usize)> = vec![];
+    for value in values {
+        match clusters.last_mut() {
+            Some(cluster) if value - cluster.1 <= max_gap => cluster.1 = value,
+            _ => clusters.push((value, value)),
+        }
+    }
+
+    IntervalSet::from_ranges(clusters)
+}
+
+/// Writes rules in the `name: a-b or c-d` notes format that `parse_rules` reads
+fn format_rules(rules: &[TicketRule]) -> String {
+    rules
+        .iter()
+        .map(|rule| format!("{}: {}", rule.name, rule.ranges))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The rules section may be left out for ticket dumps that have no notes
 fn parse_tickets(raw_tickets: String) -> Tickets {
-    let splits: Vec<&str> = raw_tickets.split("\n\n").collect();
+    let mut splits: Vec<&str> = raw_tickets.split("\n\n").collect();
+    if splits[0].starts_with("your ticket:") {
+        splits.insert(0, "");
+    }
     let raw_rules: Vec<&str> = splits[0].lines().collect();
     let raw_your_ticket: Vec<&str> = splits[1].lines().collect();
     let raw_nearby_tickets: Vec<&str> = splits[2].lines().collect();
@@ -173,24 +805,12 @@ fn parse_rules(raw_rules: Vec<&str>) -> Vec<TicketRule> {
         .map(|rule| {
             let colon_i = rule.find(":").unwrap();
             let (name, rest) = rule.split_at(colon_i);
-            let raw_ranges: Vec<&str> = rest.trim().split(" or ").collect();
-            let ranges = raw_ranges
-                .into_iter()
-                .map(|range| {
-                    let dash_i = range.find("-").unwrap();
-                    let (raw_low, raw_high) = range.split_at(dash_i);
-                    let filter_chars: &[_] = &[':', '-'];
-                    let low = raw_low.trim_matches(filter_chars).trim();
-                    let high = raw_high.trim_matches(filter_chars).trim();
-
-                    (low.parse().unwrap(), high.parse().unwrap())
-                })
-                .collect();
+            let ranges = rest.trim_start_matches(':').parse().unwrap();
 
             TicketRule {
                 name: name.to_string(),
                 column: None,
-                ranges: ranges,
+                ranges,
             }
         })
         .collect()
@@ -236,24 +856,24 @@ nearby tickets:
 
     #[test]
     fn combine_ranges_test() {
-        let expected = vec![(1, 3), (5, 11), (13, 50)];
+        let expected = IntervalSet::from_ranges(vec![(1, 3), (5, 11), (13, 50)]);
 
         let tickets = Tickets {
             rules: vec![
                 TicketRule {
                     name: "class".to_string(),
                     column: None,
-                    ranges: vec![(1, 3), (5, 7)],
+                    ranges: IntervalSet::from_ranges(vec![(1, 3), (5, 7)]),
                 },
                 TicketRule {
                     name: "row".to_string(),
                     column: None,
-                    ranges: vec![(6, 11), (33, 44)],
+                    ranges: IntervalSet::from_ranges(vec![(6, 11), (33, 44)]),
                 },
                 TicketRule {
                     name: "seat".to_string(),
                     column: None,
-                    ranges: vec![(13, 40), (45, 50)],
+                    ranges: IntervalSet::from_ranges(vec![(13, 40), (45, 50)]),
                 },
             ],
             your_ticket: vec![],
@@ -271,17 +891,17 @@ nearby tickets:
                 TicketRule {
                     name: "class".to_string(),
                     column: None,
-                    ranges: vec![(1, 3), (5, 7)],
+                    ranges: IntervalSet::from_ranges(vec![(1, 3), (5, 7)]),
                 },
                 TicketRule {
                     name: "row".to_string(),
                     column: None,
-                    ranges: vec![(6, 11), (33, 44)],
+                    ranges: IntervalSet::from_ranges(vec![(6, 11), (33, 44)]),
                 },
                 TicketRule {
                     name: "seat".to_string(),
                     column: None,
-                    ranges: vec![(13, 40), (45, 50)],
+                    ranges: IntervalSet::from_ranges(vec![(13, 40), (45, 50)]),
                 },
             ],
             your_ticket: vec![7, 1, 14],
@@ -327,24 +947,24 @@ nearby tickets:
                 TicketRule {
                     name: "class".to_string(),
                     column: None,
-                    ranges: vec![(0, 1), (4, 19)],
+                    ranges: IntervalSet::from_ranges(vec![(0, 1), (4, 19)]),
                 },
                 TicketRule {
                     name: "row".to_string(),
                     column: None,
-                    ranges: vec![(0, 5), (8, 19)],
+                    ranges: IntervalSet::from_ranges(vec![(0, 5), (8, 19)]),
                 },
                 TicketRule {
                     name: "seat".to_string(),
                     column: None,
-                    ranges: vec![(0, 13), (16, 19)],
+                    ranges: IntervalSet::from_ranges(vec![(0, 13), (16, 19)]),
                 },
             ],
             your_ticket: vec![11, 12, 13],
             nearby_tickets: vec![vec![3, 9, 18], vec![15, 1, 5], vec![5, 14, 9]],
         };
         let mut expected = tickets.clone();
-        let actual = map_columns(&tickets);
+        let actual = map_columns(&tickets).unwrap();
         expected.rules[0].column = Some(1);
         expected.rules[1].column = Some(0);
         expected.rules[2].column = Some(2);
@@ -356,6 +976,190 @@ nearby tickets:
         assert_eq!(actual, expected);
     }
 
+    fn rule(name: &str, ranges: Vec<(usize, usize)>) -> TicketRule {
+        TicketRule {
+            name: name.to_string(),
+            column: None,
+            ranges: IntervalSet::from_ranges(ranges),
+        }
+    }
+
+    #[test]
+    fn assign_columns_ambiguous() {
+        let tickets = Tickets {
+            rules: vec![
+                rule("class", vec![(0, 10)]),
+                rule("row", vec![(0, 10)]),
+                rule("seat", vec![(20, 30)]),
+            ],
+            your_ticket: vec![1, 2, 25],
+            nearby_tickets: vec![vec![3, 4, 21]],
+        };
+        let expected = ColumnAssignment::Ambiguous(vec![vec![0, 1, 2], vec![1, 0, 2]]);
+        let actual = assign_columns(&tickets, 5).unwrap();
+
+        assert_eq!(actual, expected);
+        assert_eq!(
+            map_columns(&tickets),
+            Err(ColumnError::Ambiguous(vec![vec![0, 1, 2], vec![1, 0, 2]]))
+        );
+        assert_eq!(assign_columns(&tickets, 0), Ok(expected));
+    }
+
+    #[test]
+    fn assign_columns_limit() {
+        let tickets = Tickets {
+            rules: vec![
+                rule("class", vec![(0, 10)]),
+                rule("row", vec![(0, 10)]),
+                rule("seat", vec![(0, 10)]),
+            ],
+            your_ticket: vec![1, 2, 3],
+            nearby_tickets: vec![],
+        };
+
+        match assign_columns(&tickets, 4).unwrap() {
+            ColumnAssignment::Ambiguous(found) => assert_eq!(found.len(), 4),
+            other => panic!("Expected ambiguous assignment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn assign_columns_large_unique() {
+        // Column i fits rule size - 1 - i and the two rules below it, so only
+        // the anti-diagonal works, but trying the lower rules first dead ends
+        // late enough to make naive backtracking exponential.
+        let size = 60;
+        let fits = |column: usize, rule_i: usize| {
+            let own = size - 1 - column;
+            rule_i <= own && rule_i + 2 >= own
+        };
+        let rules = (0..size)
+            .map(|rule_i| {
+                let ranges = (0..size)
+                    .filter(|&column| fits(column, rule_i))
+                    .map(|column| (column, column))
+                    .collect();
+                rule(&format!("rule{}", rule_i), ranges)
+            })
+            .collect();
+        let tickets = Tickets {
+            rules,
+            your_ticket: (0..size).collect(),
+            nearby_tickets: vec![],
+        };
+        let expected = ColumnAssignment::Unique((0..size).rev().collect());
+        let actual = assign_columns(&tickets, 2).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn assign_columns_no_matching_rule() {
+        let tickets = Tickets {
+            rules: vec![rule("class", vec![(0, 10)]), rule("row", vec![(20, 30)])],
+            your_ticket: vec![5, 8],
+            nearby_tickets: vec![vec![6, 25]],
+        };
+        let expected = Err(ColumnError::Unsatisfiable(vec![1]));
+        let actual = assign_columns(&tickets, 1);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn assign_columns_not_enough_rules() {
+        let tickets = Tickets {
+            rules: vec![
+                rule("class", vec![(0, 10)]),
+                rule("row", vec![(20, 30)]),
+                rule("seat", vec![(20, 30)]),
+            ],
+            your_ticket: vec![5, 8, 25],
+            nearby_tickets: vec![],
+        };
+        let expected = Err(ColumnError::Unsatisfiable(vec![0, 1]));
+        let actual = assign_columns(&tickets, 1);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn validation_report_example() {
+        let tickets = parse_tickets(EXAMPLE_TICKET.to_string());
+        let expected = vec![
+            TicketReport {
+                ticket: 0,
+                invalid_fields: vec![],
+            },
+            TicketReport {
+                ticket: 1,
+                invalid_fields: vec![InvalidField {
+                    column: 1,
+                    value: 4,
+                    near_miss: Some(NearMiss {
+                        rule: "class".to_string(),
+                        range: (1, 3),
+                        off_by: 1,
+                    }),
+                }],
+            },
+            TicketReport {
+                ticket: 2,
+                invalid_fields: vec![InvalidField {
+                    column: 0,
+                    value: 55,
+                    near_miss: None,
+                }],
+            },
+            TicketReport {
+                ticket: 3,
+                invalid_fields: vec![InvalidField {
+                    column: 2,
+                    value: 12,
+                    near_miss: Some(NearMiss {
+                        rule: "row".to_string(),
+                        range: (6, 11),
+                        off_by: 1,
+                    }),
+                }],
+            },
+        ];
+        let actual = validation_report(&tickets, 2);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn render_report_table_example() {
+        let expected = "ticket  column  value  closest rule  range  off by
+1       1       4      class         1-3    1
+2       0       55     -             -      -
+3       2       12     row           6-11   1
+1 of 4 tickets valid";
+        let tickets = parse_tickets(EXAMPLE_TICKET.to_string());
+        let actual = render_report_table(&validation_report(&tickets, 2));
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn render_report_json_example() {
+        let expected = concat!(
+            "[{\"ticket\":0,\"valid\":true,\"invalid_fields\":[]},",
+            "{\"ticket\":1,\"valid\":false,\"invalid_fields\":[{\"column\":1,\"value\":4,",
+            "\"near_miss\":{\"rule\":\"class\",\"low\":1,\"high\":3,\"off_by\":1}}]},",
+            "{\"ticket\":2,\"valid\":false,\"invalid_fields\":[{\"column\":0,\"value\":55,",
+            "\"near_miss\":null}]},",
+            "{\"ticket\":3,\"valid\":false,\"invalid_fields\":[{\"column\":2,\"value\":12,",
+            "\"near_miss\":{\"rule\":\"row\",\"low\":6,\"high\":11,\"off_by\":1}}]}]"
+        );
+        let tickets = parse_tickets(EXAMPLE_TICKET.to_string());
+        let actual = render_report_json(&validation_report(&tickets, 2));
+
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn filter_valid_tickets_test() {
         let expected: Vec<Vec<usize>> = vec![vec![7, 3, 47], vec![7, 1, 14]];
@@ -365,6 +1169,150 @@ nearby tickets:
         assert_eq!(actual, expected);
     }
 
+    static EXAMPLE_TICKET_2: &str = "class: 0-1 or 4-19
+row: 0-5 or 8-19
+seat: 0-13 or 16-19
+
+your ticket:
+11,12,13
+
+nearby tickets:
+3,9,18
+15,1,5
+5,14,9";
+
+    #[test]
+    fn translate_tickets_test() {
+        let record = |values: [usize; 3]| TicketRecord {
+            fields: vec![
+                ("class".to_string(), values[0]),
+                ("row".to_string(), values[1]),
+                ("seat".to_string(), values[2]),
+            ],
+        };
+        let expected = TranslatedTickets {
+            your_ticket: record([12, 11, 13]),
+            nearby_tickets: vec![record([9, 3, 18]), record([1, 15, 5]), record([14, 5, 9])],
+        };
+        let tickets = parse_tickets(EXAMPLE_TICKET_2.to_string());
+        let actual = translate_tickets(&tickets).unwrap();
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual.your_ticket.get("row"), Some(11));
+        assert_eq!(actual.your_ticket.get("train"), None);
+    }
+
+    #[test]
+    fn translate_tickets_keeps_copies_of_your_ticket() {
+        let mut tickets = parse_tickets(EXAMPLE_TICKET_2.to_string());
+        tickets.nearby_tickets.push(tickets.your_ticket.clone());
+        let actual = translate_tickets(&tickets).unwrap();
+
+        assert_eq!(actual.nearby_tickets.len(), 4);
+        assert_eq!(actual.nearby_tickets[3], actual.your_ticket);
+    }
+
+    #[test]
+    fn records_to_csv_test() {
+        let expected = "class,row,seat
+12,11,13
+9,3,18
+1,15,5
+14,5,9";
+        let tickets = parse_tickets(EXAMPLE_TICKET_2.to_string());
+        let actual = records_to_csv(&translate_tickets(&tickets).unwrap());
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn records_to_json_with_prefix() {
+        let expected = concat!(
+            "{\"your_ticket\":{\"seat\":13},",
+            "\"nearby_tickets\":[{\"seat\":18},{\"seat\":5},{\"seat\":9}]}"
+        );
+        let tickets = parse_tickets(EXAMPLE_TICKET_2.to_string());
+        let translated = translate_tickets(&tickets).unwrap().with_prefix("se");
+        let actual = records_to_json(&translated);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn escape_csv_test() {
+        assert_eq!(escape_csv("seat"), "seat");
+        assert_eq!(escape_csv("row, seat"), "\"row, seat\"");
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    static EXAMPLE_DUMP: &str = "your ticket:
+2,40,3
+
+nearby tickets:
+1,41,150
+4,44,155
+3,90,9
+20,91,160";
+
+    #[test]
+    fn parse_tickets_without_notes() {
+        let tickets = parse_tickets(EXAMPLE_DUMP.to_string());
+
+        assert_eq!(tickets.rules, vec![]);
+        assert_eq!(tickets.your_ticket, vec![2, 40, 3]);
+        assert_eq!(tickets.nearby_tickets.len(), 4);
+    }
+
+    #[test]
+    fn infer_rules_test() {
+        let expected = "column 0: 1-4 or 20-20
+column 1: 40-44 or 90-91
+column 2: 3-3 or 9-9 or 150-160";
+        let tickets = parse_tickets(EXAMPLE_DUMP.to_string());
+        let inference = infer_rules(&tickets, 5, 0.5);
+        let actual = format_rules(&inference.rules);
+
+        assert_eq!(actual, expected);
+        assert_eq!(inference.overlaps, vec![]);
+
+        let reparsed = parse_rules(actual.lines().collect());
+        for (rule, inferred) in reparsed.iter().zip(inference.rules.iter()) {
+            assert_eq!(rule.name, inferred.name);
+            assert_eq!(rule.ranges, inferred.ranges);
+        }
+    }
+
+    #[test]
+    fn infer_rules_flags_overlaps() {
+        let tickets = parse_tickets(
+            "your ticket:
+1,2,50
+
+nearby tickets:
+4,5,60
+10,9,70"
+                .to_string(),
+        );
+        let expected = vec![ColumnOverlap {
+            columns: (0, 1),
+            shared: 1.0,
+        }];
+        let actual = infer_rules(&tickets, 10, 0.5).overlaps;
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn infer_rules_uses_valid_tickets() {
+        let tickets = parse_tickets(EXAMPLE_TICKET.to_string());
+        let actual = format_rules(&infer_rules(&tickets, 0, 0.5).rules);
+
+        assert_eq!(
+            actual,
+            "column 0: 7-7\ncolumn 1: 1-1 or 3-3\ncolumn 2: 14-14 or 47-47"
+        );
+    }
+
     // Part2
     #[test]
     fn product_departure_from_input() {